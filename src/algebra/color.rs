@@ -1,11 +1,103 @@
 use std::{marker::PhantomData, ops::{Add, Div, Mul, AddAssign}, iter::Sum};
 use image::{Rgb, Rgb32FImage};
-use super::vec3::Vec3;
+use super::{matrix3::Matrix3, vec3::Vec3};
 
-#[derive(Copy, Clone)]
+/// Linear light with Rec.709 / sRGB primaries and a D65 white point, the space the renderer works in
+#[derive(Copy, Clone, Debug)]
 pub struct Linear;
-#[derive(Copy, Clone)]
+/// Rec.709 primaries encoded with the sRGB transfer function (IEC 61966-2-1)
+#[derive(Copy, Clone, Debug)]
 pub struct SRgb;
+/// Linear light with ACES AP1 primaries and the ACES (~D60) white point
+#[derive(Copy, Clone, Debug)]
+pub struct AcesCg;
+/// DCI-P3 primaries with a D65 white point, encoded with the sRGB transfer function
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayP3;
+
+/// A color space, described by how to get to and from linear Rec.709
+pub trait ColorSpace {
+    /// From linear values in this space to `Linear`
+    const TO_LINEAR_REC709: Matrix3;
+    /// From `Linear` to linear values in this space
+    const FROM_LINEAR_REC709: Matrix3;
+    /// Transfer function from linear values to the encoded values of this space
+    fn encode(value: f64) -> f64 {
+        value
+    }
+    /// Transfer function from encoded values of this space to linear values
+    fn decode(value: f64) -> f64 {
+        value
+    }
+}
+
+impl ColorSpace for Linear {
+    const TO_LINEAR_REC709: Matrix3 = Matrix3::IDENTITY;
+    const FROM_LINEAR_REC709: Matrix3 = Matrix3::IDENTITY;
+}
+impl ColorSpace for SRgb {
+    const TO_LINEAR_REC709: Matrix3 = Matrix3::IDENTITY;
+    const FROM_LINEAR_REC709: Matrix3 = Matrix3::IDENTITY;
+    fn encode(value: f64) -> f64 {
+        srgb_encode(value)
+    }
+    fn decode(value: f64) -> f64 {
+        srgb_decode(value)
+    }
+}
+// Includes the Bradford chromatic adaptation between D65 and the ACES white point
+impl ColorSpace for AcesCg {
+    const TO_LINEAR_REC709: Matrix3 = Matrix3([
+        [1.7050509927, -0.6217921207, -0.0832588720],
+        [-0.1302564175, 1.1408047366, -0.0105483191],
+        [-0.0240033568, -0.1289689761, 1.1529723329],
+    ]);
+    const FROM_LINEAR_REC709: Matrix3 = Matrix3([
+        [0.6130974024, 0.3395231462, 0.0473794514],
+        [0.0701937225, 0.9163538791, 0.0134523985],
+        [0.0206155929, 0.1095697729, 0.8698146342],
+    ]);
+}
+impl ColorSpace for DisplayP3 {
+    const TO_LINEAR_REC709: Matrix3 = Matrix3([
+        [1.2249401763, -0.2249401763, 0.],
+        [-0.0420569547, 1.0420569547, 0.],
+        [-0.0196375546, -0.0786360456, 1.0982736002],
+    ]);
+    const FROM_LINEAR_REC709: Matrix3 = Matrix3([
+        [0.8224619687, 0.1775380313, 0.],
+        [0.0331941989, 0.9668058011, 0.],
+        [0.0170826307, 0.0723974407, 0.9105199286],
+    ]);
+    fn encode(value: f64) -> f64 {
+        srgb_encode(value)
+    }
+    fn decode(value: f64) -> f64 {
+        srgb_decode(value)
+    }
+}
+
+/// Weights of linear Rec.709 red, green and blue in the luminance
+pub const REC_709_LUMINANCE: Vec3 = Vec3::new(0.2126, 0.7152, 0.0722);
+
+/// The piecewise sRGB transfer function from IEC 61966-2-1, linear to encoded.
+/// Negative values are mirrored so out of gamut colors survive a round trip.
+pub fn srgb_encode(value: f64) -> f64 {
+    if value.abs() <= 0.0031308 {
+        value * 12.92
+    } else {
+        value.signum() * (1.055 * value.abs().powf(1. / 2.4) - 0.055)
+    }
+}
+
+/// The inverse of `srgb_encode`, encoded to linear
+pub fn srgb_decode(value: f64) -> f64 {
+    if value.abs() <= 0.04045 {
+        value / 12.92
+    } else {
+        value.signum() * ((value.abs() + 0.055) / 1.055).powf(2.4)
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Color<Space = Linear>{
@@ -17,6 +109,9 @@ impl<Space> Color<Space>{
     pub fn clamp_to_rgb(&self) -> Rgb<u8>{
         Rgb([(self.rgb.x*255.).min(255.) as u8, (self.rgb.y*255.).min(255.) as u8, (self.rgb.z*255.).min(255.) as u8])
     }
+    pub fn to_rgb_f32(&self) -> Rgb<f32>{
+        Rgb([self.rgb.x as f32, self.rgb.y as f32, self.rgb.z as f32])
+    }
     pub const BLACK : Color<Space> = Color::<Space>{
         rgb: Vec3::ZEROS,
         space : PhantomData::<Space>
@@ -27,30 +122,28 @@ impl<Space> Color<Space>{
     };
 }
 
-impl Color<Linear>{
-    pub fn to_srgb(self) -> Color<SRgb>{
-        let inv_gamma = 1./2.2;
+impl<Space: ColorSpace> Color<Space>{
+    /// Converts to another color space, going through linear Rec.709
+    pub fn convert<Target: ColorSpace>(self) -> Color<Target>{
+        let linear = Space::TO_LINEAR_REC709 * self.rgb.map(Space::decode);
         Color{
-            rgb: Vec3{
-                x: self.rgb.x.powf(inv_gamma),
-                y: self.rgb.y.powf(inv_gamma),
-                z: self.rgb.z.powf(inv_gamma),
-            },
-            space: PhantomData::<SRgb>,
+            rgb: (Target::FROM_LINEAR_REC709 * linear).map(Target::encode),
+            space: PhantomData::<Target>,
         }
     }
 }
+
+impl Color<Linear>{
+    pub fn to_srgb(self) -> Color<SRgb>{
+        self.convert()
+    }
+    pub fn luminance(&self) -> f64{
+        self.rgb.dot(&REC_709_LUMINANCE)
+    }
+}
 impl Color<SRgb>{
     pub fn to_linear(self) -> Color<Linear>{
-        let gamma = 2.2;
-        Color{
-            rgb: Vec3{
-                x: self.rgb.x.powf(gamma),
-                y: self.rgb.y.powf(gamma),
-                z: self.rgb.z.powf(gamma),
-            },
-            space: PhantomData::<Linear>,
-        }
+        self.convert()
     }
 }
 
@@ -87,6 +180,16 @@ impl<Space> Div<f64> for Color<Space>{
         }
     }
 }
+impl<Space> Mul<f64> for Color<Space>{
+    type Output = Color<Space>;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Color{
+            rgb: self.rgb * rhs,
+            ..self
+        }
+    }
+}
 impl<Space> From<Vec3> for Color<Space>{
     fn from(value: Vec3) -> Self {
         Color { rgb: value, space: PhantomData::<Space> }
@@ -117,27 +220,61 @@ pub trait SpaceCast{
 
 impl SpaceCast for Rgb32FImage{
     fn srgb_to_linear(mut self) -> Self{
-        let gamma = 2.2;
         self
             .pixels_mut()
-            .for_each(|pixel| {
-                pixel.0[0] = pixel.0[0].powf(gamma);
-                pixel.0[1] = pixel.0[1].powf(gamma);
-                pixel.0[2] = pixel.0[2].powf(gamma);
-            });
+            .for_each(|pixel| pixel.0 = pixel.0.map(|value| srgb_decode(value as f64) as f32));
         self
     }
-    
-    fn linear_to_srgb(mut self) -> Self{    
-        let inv_gamma = 1./2.2;
+
+    fn linear_to_srgb(mut self) -> Self{
         self
             .pixels_mut()
-            .for_each(|pixel| {
-                pixel.0[0] = pixel.0[0].powf(inv_gamma);
-                pixel.0[1] = pixel.0[1].powf(inv_gamma);
-                pixel.0[2] = pixel.0[2].powf(inv_gamma);
-            });
+            .for_each(|pixel| pixel.0 = pixel.0.map(|value| srgb_encode(value as f64) as f32));
         self
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{srgb_decode, srgb_encode, AcesCg, Color, ColorSpace, DisplayP3, Linear, SRgb};
+    use crate::algebra::{matrix3::Matrix3, vec3::Vec3};
+
+    fn assert_identity(matrix: Matrix3) {
+        for (row, values) in matrix.0.iter().enumerate() {
+            for (column, value) in values.iter().enumerate() {
+                let expected = if row == column { 1. } else { 0. };
+                assert!((value - expected).abs() < 1e-6, "{:?} is not the identity", matrix);
+            }
+        }
+    }
+
+    fn assert_round_trip<Space: ColorSpace>() {
+        assert_identity(Space::TO_LINEAR_REC709 * Space::FROM_LINEAR_REC709);
+        assert_identity(Space::FROM_LINEAR_REC709 * Space::TO_LINEAR_REC709);
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        for value in [-2., -0.5, -0.001, 0., 0.0031308, 0.002, 0.04045, 0.2, 0.5, 1., 4.] {
+            assert!((srgb_decode(srgb_encode(value)) - value).abs() < 1e-12, "{} changed", value);
+        }
+        assert!((srgb_encode(1.) - 1.).abs() < 1e-12);
+        assert!((srgb_encode(0.18) - 0.4613561).abs() < 1e-6);
+    }
+
+    #[test]
+    fn matrices_are_inverses() {
+        assert_round_trip::<Linear>();
+        assert_round_trip::<SRgb>();
+        assert_round_trip::<AcesCg>();
+        assert_round_trip::<DisplayP3>();
+    }
+
+    #[test]
+    fn white_stays_white() {
+        let white = Color::<Linear>::from(Vec3::ONES);
+        for rgb in [white.convert::<SRgb>().rgb, white.convert::<AcesCg>().rgb, white.convert::<DisplayP3>().rgb] {
+            assert!((rgb - Vec3::ONES).magnitude() < 1e-6, "{} is not white", rgb);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/// Picks values in [0, 1) with a density proportional to a piecewise constant function,
+/// see Physically Based Rendering, section 13.6.7
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    function: Vec<f64>,
+    /// Running integral of the function, divided by the total so the last value is 1
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Negative values count as 0. Uniform when there is nothing to integrate.
+    pub fn new(function: Vec<f64>) -> Self {
+        let function: Vec<f64> = function.into_iter().map(|value| value.max(0.)).collect();
+        let count = function.len() as f64;
+        let mut cdf = Vec::with_capacity(function.len() + 1);
+        cdf.push(0.);
+        for value in &function {
+            cdf.push(cdf.last().unwrap() + value / count);
+        }
+        let integral = *cdf.last().unwrap();
+        for (index, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0. { *value / integral } else { index as f64 / count };
+        }
+        Self { function, cdf, integral }
+    }
+
+    /// Average of the function over [0, 1)
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a uniform `u` to a value with this distribution, returned with its density and the piece it lies in
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let index = (self.cdf.partition_point(|&cdf| cdf <= u) - 1).min(self.function.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0. { (u - self.cdf[index]) / width } else { 0. };
+        let value = ((index as f64 + offset) / self.function.len() as f64).min(1. - f64::EPSILON);
+        (value, self.pdf_of_piece(index), index)
+    }
+
+    /// Density of sampling `value`
+    pub fn pdf(&self, value: f64) -> f64 {
+        self.pdf_of_piece(self.piece(value))
+    }
+
+    fn piece(&self, value: f64) -> usize {
+        ((value * self.function.len() as f64) as usize).min(self.function.len() - 1)
+    }
+
+    fn pdf_of_piece(&self, index: usize) -> f64 {
+        if self.integral > 0. { self.function[index] / self.integral } else { 1. }
+    }
+}
+
+/// Picks points in the unit square with a density proportional to a piecewise constant function,
+/// first the row by the sum of its values, then the column within that row
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `function` holds `width` values for each row, from the top row down
+    pub fn new(function: &[f64], width: usize) -> Self {
+        let rows: Vec<Distribution1D> = function
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Self { rows, marginal }
+    }
+
+    /// Maps a uniform `(u, v)` to a point `(x, y)` with this distribution, returned with its density
+    pub fn sample(&self, (u, v): (f64, f64)) -> ((f64, f64), f64) {
+        let (y, marginal_pdf, row) = self.marginal.sample(v);
+        let (x, row_pdf, _column) = self.rows[row].sample(u);
+        ((x, y), marginal_pdf * row_pdf)
+    }
+
+    /// Density of sampling the point `(x, y)`
+    pub fn pdf(&self, (x, y): (f64, f64)) -> f64 {
+        let row = &self.rows[self.marginal.piece(y)];
+        if self.marginal.integral() > 0. {
+            row.function[row.piece(x)] / self.marginal.integral()
+        } else {
+            1.
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::ops::Mul;
+
+use super::vec3::Vec3;
+
+/// Row-major 3x3 matrix
+#[derive(Debug, Clone, Copy)]
+pub struct Matrix3(pub [[f64; 3]; 3]);
+
+impl Matrix3 {
+    pub const IDENTITY: Matrix3 = Matrix3([[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]]);
+}
+
+impl Mul<Vec3> for Matrix3 {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        let m = self.0;
+        Vec3 {
+            x: m[0][0] * rhs.x + m[0][1] * rhs.y + m[0][2] * rhs.z,
+            y: m[1][0] * rhs.x + m[1][1] * rhs.y + m[1][2] * rhs.z,
+            z: m[2][0] * rhs.x + m[2][1] * rhs.y + m[2][2] * rhs.z,
+        }
+    }
+}
+
+impl Mul<Matrix3> for Matrix3 {
+    type Output = Matrix3;
+    fn mul(self, rhs: Matrix3) -> Self::Output {
+        let (a, b) = (self.0, rhs.0);
+        Matrix3(std::array::from_fn(|row| {
+            std::array::from_fn(|column| (0..3).map(|i| a[row][i] * b[i][column]).sum())
+        }))
+    }
+}
@@ -3,4 +3,6 @@ pub mod vec3;
 pub mod quaternion;
 pub mod axis;
 pub mod ray;
-pub mod color;
\ No newline at end of file
+pub mod color;
+pub mod matrix3;
+pub mod distribution;
\ No newline at end of file
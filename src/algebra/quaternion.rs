@@ -16,9 +16,25 @@ impl Quaternion {
     pub fn new() -> Self{
         Quaternion{real: 0., i: 0., j: 0., k: 0.}
     }
+    pub const IDENTITY: Quaternion = Quaternion{real: 1., i: 0., j: 0., k: 0.};
+
+    pub fn from_axis_angle(axis: &Vec3, angle_radians: f64) -> Self {
+        Quaternion::from_vector((angle_radians / 2.).cos(), axis.normalize() * (angle_radians / 2.).sin())
+    }
+    /// Rotation around x, then y, then z
+    pub fn from_euler_angles(angles_radians: &Vec3) -> Self {
+        Quaternion::from_axis_angle(&Vec3::Z, angles_radians.z)
+            * Quaternion::from_axis_angle(&Vec3::Y, angles_radians.y)
+            * Quaternion::from_axis_angle(&Vec3::X, angles_radians.x)
+    }
     pub fn from_unit_vectors(from: &Vec3, to: &Vec3) -> Self {
         let d = from.dot(to);
         let w = from.cross(to);
+        if d + f64::sqrt(d * d + w.dot(&w)) < 1e-12 {
+            // Opposite vectors, any axis perpendicular to them works for a half turn
+            let axis = if from.x.abs() < 0.9 { from.cross(&Vec3::X) } else { from.cross(&Vec3::Y) };
+            return Quaternion::from_vector(0., axis.normalize());
+        }
 
         Quaternion::from_vector(d + f64::sqrt(d * d + w.dot(&w)), w).normalize()
     }
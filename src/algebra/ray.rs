@@ -2,36 +2,65 @@ use crate::Vec3;
 
 use super::quaternion::Quaternion;
 
+/// Shadow rays stop this fraction of their length before the point they test, so the surface there does not count
+const SHADOW_EPSILON: f64 = 1e-4;
+
+/// Points closer to 0 than this are moved off surfaces by a fixed distance instead of by units in the last place
+const OFFSET_ORIGIN: f64 = 1. / 32.;
+/// Fixed offset near 0, matching the offset in units in the last place at `OFFSET_ORIGIN`
+const OFFSET_FLOAT_SCALE: f64 = 1. / (1u64 << 41) as f64;
+/// Offset in units in the last place, far beyond the rounding errors of a hit position for any sensible scene scale
+const OFFSET_INT_SCALE: f64 = 65536.;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction_unit: Vec3,
+    /// Only hits at distances from `t_min` up to `t_max` along the ray count
+    pub t_min: f64,
+    pub t_max: f64,
 }
 
 impl Ray {
+    /// A ray without an end
+    #[inline]
+    pub fn new(origin: Vec3, direction_unit: Vec3) -> Self {
+        Ray { origin, direction_unit, t_min: 0., t_max: f64::INFINITY }
+    }
+
+    /// A ray leaving a surface at `position`, starting just off it on the side of the geometric normal it leaves to
+    #[inline]
+    pub fn leaving(position: Vec3, geometric_normal: Vec3, direction_unit: Vec3) -> Self {
+        Ray::new(offset_from_surface(position, geometric_normal, direction_unit), direction_unit)
+    }
+
+    /// A ray leaving a surface towards the point `distance` away, stopping just before it, as used for shadow rays
+    #[inline]
+    pub fn between(position: Vec3, geometric_normal: Vec3, direction_unit: Vec3, distance: f64) -> Self {
+        Ray { t_max: distance * (1. - SHADOW_EPSILON), ..Ray::leaving(position, geometric_normal, direction_unit) }
+    }
+
     #[inline]
     pub fn at(&self, distance: f64) -> Vec3 {
         self.origin + self.direction_unit * distance
     }
 
     #[inline]
-    pub fn reflect_specular(&self, surface_normal: Vec3, new_origin: Vec3) -> Self {
+    pub fn reflect_specular(&self, surface_normal: Vec3, new_origin: Vec3, geometric_normal: Vec3) -> Self {
         let reflected_direction =
             self.direction_unit - 2. * self.direction_unit.dot(&surface_normal) * surface_normal;
-        Ray {
-            origin: new_origin,
-            direction_unit: reflected_direction,
-        }
+        Ray::leaving(new_origin, geometric_normal, reflected_direction)
     }
 
     #[inline]
+    /// Cosine weighted direction in the hemisphere around the normal, for a uniform 2D sample
     pub fn reflect_diffuse(
         &self,
         surface_normal: Vec3,
         new_origin: Vec3,
+        geometric_normal: Vec3,
+        (random_u, random_v): (f64, f64),
     ) -> Self {
-        let random_u = fastrand::f64();
-        let random_v = fastrand::f64();
 
         let radius = random_u.sqrt();
         let theta = 2.0 * std::f64::consts::PI * random_v;
@@ -46,14 +75,11 @@ impl Ray {
 
         align_with_normal.rotate_vector(&mut random_cos_hemisphere);
 
-        Ray {
-            origin: new_origin,
-            direction_unit: random_cos_hemisphere,
-        }
+        Ray::leaving(new_origin, geometric_normal, random_cos_hemisphere)
     }
 
     #[inline]
-    pub fn refract(&self, mut surface_normal: Vec3, new_origin: Vec3, mut ior: f64) -> Self {
+    pub fn refract(&self, mut surface_normal: Vec3, new_origin: Vec3, geometric_normal: Vec3, mut ior: f64) -> Self {
         if surface_normal.dot(&self.direction_unit) < 0. {
             ior = 1. / ior;
         } else {
@@ -64,9 +90,28 @@ impl Ray {
             * (self.direction_unit + (-self.direction_unit.dot(&surface_normal) * surface_normal));
         let r_parallel = -f64::sqrt(1. - r_orthogonal.magnitude_squared()) * surface_normal;
 
-        Ray {
-            origin: new_origin,
-            direction_unit: r_orthogonal + r_parallel,
-        }
+        Ray::leaving(new_origin, geometric_normal, r_orthogonal + r_parallel)
     }
 }
+
+/// Moves a point on a surface along the geometric normal to the side `direction` leaves to, just far enough that
+/// rounding errors in the position cannot make a ray from it hit the surface again. The distance grows with the
+/// coordinates, so it works at any scene scale. See Wächter and Binder, "A Fast and Robust Method for Avoiding
+/// Self-Intersection", Ray Tracing Gems (2019), with the constants scaled for `f64`.
+fn offset_from_surface(position: Vec3, geometric_normal: Vec3, direction: Vec3) -> Vec3 {
+    let normal = if direction.dot(&geometric_normal) < 0. { -geometric_normal } else { geometric_normal };
+    let offset = |coordinate: f64, normal: f64| {
+        if coordinate.abs() < OFFSET_ORIGIN {
+            return coordinate + OFFSET_FLOAT_SCALE * normal;
+        }
+        // Steps through the representable numbers, away from 0 for a positive bits offset
+        let steps = (OFFSET_INT_SCALE * normal) as i64;
+        let steps = if coordinate < 0. { -steps } else { steps };
+        f64::from_bits((coordinate.to_bits() as i64 + steps) as u64)
+    };
+    Vec3::new(
+        offset(position.x, normal.x),
+        offset(position.y, normal.y),
+        offset(position.z, normal.z),
+    )
+}
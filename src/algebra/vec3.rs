@@ -69,6 +69,10 @@ impl Vec3 {
     pub fn ew_max(&self, other: &Vec3) -> Vec3{
         Vec3::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
     }
+    /// Applies `function` to x, y and z
+    pub fn map(self, function: impl Fn(f64) -> f64) -> Vec3{
+        Vec3::new(function(self.x), function(self.y), function(self.z))
+    }
     pub fn clamp_to_rgb(&self) -> Rgb<u8>{
         Rgb([(self.x*255.).min(255.) as u8, (self.y*255.).min(255.) as u8, (self.z*255.).min(255.) as u8])
     }
@@ -107,6 +111,16 @@ impl From<(f64, f64, f64)> for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from(value: [f64; 3]) -> Self {
+        Vec3 {
+            x: value[0],
+            y: value[1],
+            z: value[2],
+        }
+    }
+}
+
 impl From<&Rgb<f32>> for Vec3 {
     fn from(value: &Rgb<f32>) -> Self {
         Vec3 {
@@ -270,7 +284,7 @@ impl Neg for Vec3 {
 
 impl Sum<Vec3> for Vec3{
     fn sum<I: Iterator<Item = Vec3>>(iter: I) -> Self {
-        iter.reduce(|acc, vec3| acc + vec3).unwrap()
+        iter.fold(Vec3::ZEROS, |acc, vec3| acc + vec3)
     }
 }
 
@@ -0,0 +1,232 @@
+use std::{path::Path, time::Duration};
+
+use raytracer::{
+    algebra::vec3::Vec3,
+    image::{export::{check_output, output_format, OutputColorSpace}, filter::Filter, tone_mapping::ToneMapping},
+    renderer::{render_settings::RenderSettings, sampler::SamplerKind},
+    scene::Scene,
+    world::{camera::Camera, World},
+};
+
+pub const USAGE: &str = "Usage: raytracer [OPTIONS] <SCENE.scene | MODEL.obj>
+
+Options given on the command line override the values from a scene file.
+  -m, --materials <FILE>   Material library (.mtl) for a model, defaults to <MODEL>.mtl if it exists
+  -s, --skybox <FILE>      Equirectangular skybox image
+  -o, --output <FILE>      Write the finished render to a .png, .jpg, .exr, .hdr or .pfm file
+  -W, --width <PIXELS>     Image width [default: 600]
+  -H, --height <PIXELS>    Image height [default: 400]
+      --camera <X,Y,Z>     Camera position, needs --look-at [default: 10,5,10]
+      --look-at <X,Y,Z>    Point the camera looks at [default: 0,0.1,0]
+      --fov <DEGREES>      Horizontal field of view [default: 45]
+      --min-samples <N>    Samples every pixel gets [default: 16]
+      --max-samples <N>    Samples a pixel gets at most [default: 256]
+      --noise-threshold <E>
+                           Stop sampling a pixel once its relative error is below E, 0 to always
+                           use the maximum [default: 0.02]
+      --sampler <NAME>     independent, stratified, halton, sobol or blue-noise [default: sobol]
+      --filter <NAME[:RADIUS]>
+                           box, gaussian, mitchell, lanczos or blackman-harris, RADIUS from 0.5 to 8 pixels
+                           [default: box:0.5]
+      --seed <N>           Seed for the random numbers, the same seed gives the same image [default: 0]
+      --time-limit <SECS>  Stop adding samples after this many seconds
+      --bounces <N>        Maximum number of ray bounces [default: 16]
+      --threads <N>        Number of render threads [default: all cores]
+      --tone-mapping <OP>  clamp, reinhard, extended-reinhard[:WHITE], aces or agx [default: clamp]
+      --exposure <STOPS>   Exposure adjustment before tone mapping [default: 0]
+      --color-space <NAME> srgb, display-p3 for .png and .jpg, or acescg for .exr, .hdr and .pfm
+                           [default: srgb]
+      --no-window          Do not open a preview window
+  -h, --help               Print this message";
+
+#[derive(Default)]
+pub struct Arguments {
+    pub input: String,
+    pub materials: Option<String>,
+    pub skybox: Option<String>,
+    pub output: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub camera_position: Option<Vec3>,
+    pub look_at: Option<Vec3>,
+    pub fov_degrees: Option<f64>,
+    pub min_samples: Option<usize>,
+    pub max_samples: Option<usize>,
+    pub noise_threshold: Option<f64>,
+    pub sampler: Option<SamplerKind>,
+    pub filter: Option<Filter>,
+    pub seed: Option<u64>,
+    pub time_limit: Option<Duration>,
+    pub max_bounces: Option<u8>,
+    pub threads: Option<usize>,
+    pub tone_mapping: Option<ToneMapping>,
+    pub exposure: Option<f64>,
+    pub color_space: Option<OutputColorSpace>,
+    pub no_window: bool,
+}
+
+/// Returns `None` when only the usage was asked for
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Arguments>, String> {
+    let mut arguments = Arguments::default();
+    let mut input = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-m" | "--materials" => arguments.materials = Some(value()?),
+            "-s" | "--skybox" => arguments.skybox = Some(value()?),
+            "-o" | "--output" => arguments.output = Some(value()?),
+            "-W" | "--width" => arguments.width = Some(parse_number(&arg, &value()?)?),
+            "-H" | "--height" => arguments.height = Some(parse_number(&arg, &value()?)?),
+            "--camera" => arguments.camera_position = Some(parse_vec3(&arg, &value()?)?),
+            "--look-at" => arguments.look_at = Some(parse_vec3(&arg, &value()?)?),
+            "--fov" => arguments.fov_degrees = Some(parse_number(&arg, &value()?)?),
+            "--min-samples" => arguments.min_samples = Some(parse_number(&arg, &value()?)?),
+            "--max-samples" => arguments.max_samples = Some(parse_number(&arg, &value()?)?),
+            "--noise-threshold" => arguments.noise_threshold = Some(parse_number(&arg, &value()?)?),
+            "--sampler" => arguments.sampler = Some(value()?.parse()?),
+            "--filter" => arguments.filter = Some(value()?.parse()?),
+            "--seed" => arguments.seed = Some(parse_number(&arg, &value()?)?),
+            "--time-limit" => arguments.time_limit = Some(parse_duration(&arg, &value()?)?),
+            "--bounces" => arguments.max_bounces = Some(parse_number(&arg, &value()?)?),
+            "--threads" => arguments.threads = Some(parse_number(&arg, &value()?)?),
+            "--tone-mapping" => arguments.tone_mapping = Some(value()?.parse()?),
+            "--exposure" => arguments.exposure = Some(parse_number(&arg, &value()?)?),
+            "--color-space" => arguments.color_space = Some(value()?.parse()?),
+            "--no-window" => arguments.no_window = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    arguments.input = input.ok_or("No scene or model file given".to_string())?;
+    if arguments.width == Some(0) || arguments.height == Some(0) {
+        return Err("Image size must be at least 1x1".to_string());
+    }
+    if arguments.fov_degrees.is_some_and(|fov_degrees| !(fov_degrees > 0. && fov_degrees < 180.)) {
+        return Err("--fov must be more than 0 and less than 180 degrees".to_string());
+    }
+    if arguments.max_samples == Some(0) {
+        return Err("--max-samples must be at least 1".to_string());
+    }
+    if let (Some(min_samples), Some(max_samples)) = (arguments.min_samples, arguments.max_samples) {
+        if min_samples > max_samples {
+            return Err("--min-samples must not be more than --max-samples".to_string());
+        }
+    }
+    if arguments.noise_threshold.is_some_and(|noise_threshold| !(noise_threshold >= 0. && noise_threshold.is_finite())) {
+        return Err("--noise-threshold must be a number of at least 0".to_string());
+    }
+    if arguments.exposure.is_some_and(|exposure| !exposure.is_finite()) {
+        return Err("--exposure must be a finite number".to_string());
+    }
+    // Keeping the old orientation at a new position would point the camera at something else
+    if arguments.camera_position.is_some() && arguments.look_at.is_none() {
+        return Err("--camera needs --look-at".to_string());
+    }
+    if arguments.materials.is_some() && arguments.input.ends_with(".scene") {
+        return Err("--materials only applies to models, scene files name their libraries with mtllib".to_string());
+    }
+    if arguments.threads == Some(0) {
+        return Err("--threads must be at least 1".to_string());
+    }
+    if let Some(output) = &arguments.output {
+        output_format(output)?;
+    }
+
+    Ok(Some(arguments))
+}
+
+impl Arguments {
+    /// Loads the scene file, or builds a scene around a single model, and applies the overrides
+    pub fn load_scene(&self) -> Result<Scene, String> {
+        let mut scene = if self.input.ends_with(".scene") {
+            Scene::load(&self.input)?
+        } else {
+            self.scene_from_model()?
+        };
+
+        let camera = &mut scene.world.camera;
+        if self.width.is_some() || self.height.is_some() {
+            let (width, height) = camera.resolution();
+            camera.set_resolution(self.width.unwrap_or(width), self.height.unwrap_or(height));
+        }
+        if let Some(fov_degrees) = self.fov_degrees {
+            camera.set_fov(fov_degrees.to_radians());
+        }
+        if let Some(camera_position) = self.camera_position {
+            camera.position = camera_position;
+        }
+        if let Some(look_at) = self.look_at {
+            camera.look_at(look_at)?;
+        }
+        if let Some(skybox) = &self.skybox {
+            scene.world.import_skybox_file(skybox)?;
+        }
+
+        let settings = &mut scene.settings;
+        settings.min_samples = self.min_samples.unwrap_or(settings.min_samples);
+        settings.max_samples = self.max_samples.unwrap_or(settings.max_samples);
+        settings.noise_threshold = self.noise_threshold.unwrap_or(settings.noise_threshold);
+        settings.sampler = self.sampler.unwrap_or(settings.sampler);
+        settings.filter = self.filter.unwrap_or(settings.filter);
+        settings.seed = self.seed.unwrap_or(settings.seed);
+        settings.time_limit = self.time_limit.or(settings.time_limit);
+        settings.max_bounces = self.max_bounces.unwrap_or(settings.max_bounces);
+        settings.threads = self.threads.or(settings.threads);
+        settings.tone_mapping = self.tone_mapping.unwrap_or(settings.tone_mapping);
+        settings.exposure = self.exposure.unwrap_or(settings.exposure);
+        settings.color_space = self.color_space.unwrap_or(settings.color_space);
+        // Only one of them may have been given, the other comes from the scene
+        if settings.min_samples > settings.max_samples {
+            return Err(format!("Minimum samples ({}) must not be more than maximum samples ({})", settings.min_samples, settings.max_samples));
+        }
+        settings.sampler.check_sample_count(settings.max_samples, scene.world.camera.resolution())?;
+        if let Some(output) = &self.output {
+            check_output(output, settings.color_space)?;
+        }
+        Ok(scene)
+    }
+
+    fn scene_from_model(&self) -> Result<Scene, String> {
+        let mut camera = Camera::with_resolution(45_f64.to_radians(), 600, 400);
+        camera.position = Vec3::new(10., 5., 10.);
+        camera.look_at(Vec3::new(0., 0.1, 0.))?;
+
+        let mut world = World::with_camera(camera);
+        world.import_3d_file(&self.input)?;
+
+        let sibling_materials = Path::new(&self.input).with_extension("mtl");
+        if let Some(materials) = &self.materials {
+            world.import_material_file(materials)?;
+        } else if sibling_materials.exists() {
+            world.import_material_file(&sibling_materials.to_string_lossy())?;
+        }
+
+        Ok(Scene { world, settings: RenderSettings::default() })
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse::<T>()
+        .map_err(|_| format!("Invalid value '{}' for {}", value, option))
+}
+
+fn parse_duration(option: &str, value: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(parse_number(option, value)?)
+        .map_err(|_| format!("Invalid value '{}' for {}", value, option))
+}
+
+fn parse_vec3(option: &str, value: &str) -> Result<Vec3, String> {
+    let numbers = value
+        .split(',')
+        .map(|number| parse_number::<f64>(option, number.trim()))
+        .collect::<Result<Vec<f64>, String>>()?;
+    match numbers[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("Expected X,Y,Z for {}, got '{}'", option, value)),
+    }
+}
@@ -4,7 +4,12 @@ use crate::{algebra::vec3::Vec3, material::Material};
 pub struct Hit <'a>{
     pub distance: f64,
     pub position: Vec3,
+    /// Interpolated from the vertex normals, on the same side as the geometric normal
     pub normal: Vec3,
+    /// Normal of the plane of the triangle that was hit
+    pub geometric_normal: Vec3,
+    /// Texture coordinates, interpolated from those of the vertices
+    pub uv: (f64, f64),
     pub material: &'a Material,
 }
 
@@ -0,0 +1,22 @@
+use image::{Rgb32FImage, RgbImage};
+
+use crate::algebra::{color::{Color, ColorSpace, Linear, SRgb}, vec3::Vec3};
+
+use super::tone_mapping::ToneMapping;
+
+/// Converts a linear render to 8-bit sRGB, for showing on screen or saving as png / jpeg.
+/// Exposure is in stops, every stop doubles the brightness before tone mapping.
+pub fn to_display_image(render: &Rgb32FImage, tone_mapping: ToneMapping, exposure: f64) -> RgbImage {
+    to_display_image_in::<SRgb>(render, tone_mapping, exposure)
+}
+
+/// Like `to_display_image`, but encoded for a display with the primaries and transfer function of `Space`
+pub fn to_display_image_in<Space: ColorSpace>(render: &Rgb32FImage, tone_mapping: ToneMapping, exposure: f64) -> RgbImage {
+    let exposure_factor = exposure.exp2();
+    RgbImage::from_fn(render.width(), render.height(), |x, y| {
+        let radiance = Vec3::from(render.get_pixel(x, y)) * exposure_factor;
+        Color::<Linear>::from(tone_mapping.apply(radiance))
+            .convert::<Space>()
+            .clamp_to_rgb()
+    })
+}
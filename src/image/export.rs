@@ -0,0 +1,147 @@
+use std::{borrow::Cow, fs::File, io::{BufWriter, Write}, path::Path, str::FromStr};
+
+use image::{codecs::hdr::HdrEncoder, ImageFormat, Rgb32FImage};
+
+use crate::algebra::{color::{AcesCg, Color, ColorSpace, DisplayP3, Linear, SRgb}, vec3::Vec3};
+
+use super::{display::to_display_image_in, tone_mapping::ToneMapping};
+
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    OpenExr,
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    /// High dynamic range formats store the linear radiance, without clamping or sRGB conversion
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, OutputFormat::OpenExr | OutputFormat::Hdr | OutputFormat::Pfm)
+    }
+}
+
+/// The color space renders are saved in
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputColorSpace {
+    /// sRGB for the 8-bit formats, linear Rec.709 for the high dynamic range formats
+    #[default]
+    Srgb,
+    /// For wide gamut displays, only for the 8-bit formats
+    DisplayP3,
+    /// For compositing and grading, only for the high dynamic range formats
+    AcesCg,
+}
+
+impl OutputColorSpace {
+    /// Display P3 is display encoded and ACEScg is linear, so each fits only one kind of format
+    pub fn check_format(&self, format: &OutputFormat) -> Result<(), String> {
+        match self {
+            OutputColorSpace::DisplayP3 if format.is_hdr() => Err("Display P3 output needs a .png or .jpg file".to_string()),
+            OutputColorSpace::AcesCg if !format.is_hdr() => Err("ACEScg output needs an .exr, .hdr or .pfm file".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for OutputColorSpace {
+    type Err = String;
+
+    /// Parses `srgb`, `display-p3` or `acescg`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().replace('_', "-").as_str() {
+            "srgb" => Ok(OutputColorSpace::Srgb),
+            "display-p3" => Ok(OutputColorSpace::DisplayP3),
+            "acescg" => Ok(OutputColorSpace::AcesCg),
+            _ => Err(format!("Unknown color space '{}', expected srgb, display-p3 or acescg", input)),
+        }
+    }
+}
+
+/// Picks the image format from the file extension, only formats the renderer can write are accepted
+pub fn output_format(path: &str) -> Result<OutputFormat, String> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("png") => Ok(OutputFormat::Png),
+        Some("jpg") | Some("jpeg") => Ok(OutputFormat::Jpeg),
+        Some("exr") => Ok(OutputFormat::OpenExr),
+        Some("hdr") => Ok(OutputFormat::Hdr),
+        Some("pfm") => Ok(OutputFormat::Pfm),
+        _ => Err(format!("Unsupported output format for {}, expected .png, .jpg, .exr, .hdr or .pfm", path)),
+    }
+}
+
+/// Checks that the renderer can write `path` in `color_space`, to fail before rendering rather than after
+pub fn check_output(path: &str, color_space: OutputColorSpace) -> Result<(), String> {
+    color_space.check_format(&output_format(path)?)
+}
+
+/// Saves a linear render. Low dynamic range formats are tone mapped and display encoded,
+/// high dynamic range formats get the unmodified radiance, only converted to other primaries for ACEScg.
+pub fn save_render(
+    render: &Rgb32FImage,
+    path: &str,
+    tone_mapping: ToneMapping,
+    exposure: f64,
+    color_space: OutputColorSpace,
+) -> Result<(), String> {
+    let format = output_format(path)?;
+    color_space.check_format(&format)?;
+    let display_image = || match color_space {
+        OutputColorSpace::DisplayP3 => to_display_image_in::<DisplayP3>(render, tone_mapping, exposure),
+        _ => to_display_image_in::<SRgb>(render, tone_mapping, exposure),
+    };
+    let linear_image = match color_space {
+        OutputColorSpace::AcesCg => Cow::Owned(convert_linear::<AcesCg>(render)),
+        _ => Cow::Borrowed(render),
+    };
+    let result = match format {
+        OutputFormat::Png => display_image()
+            .save_with_format(path, ImageFormat::Png)
+            .map_err(|error| error.to_string()),
+        OutputFormat::Jpeg => display_image()
+            .save_with_format(path, ImageFormat::Jpeg)
+            .map_err(|error| error.to_string()),
+        OutputFormat::OpenExr => linear_image
+            .save_with_format(path, ImageFormat::OpenExr)
+            .map_err(|error| error.to_string()),
+        OutputFormat::Hdr => save_hdr(&linear_image, path),
+        OutputFormat::Pfm => save_pfm(&linear_image, path),
+    };
+    result.map_err(|error| format!("Could not save render to {}: {}", path, error))
+}
+
+/// Converts a linear Rec.709 render to another linear color space
+fn convert_linear<Space: ColorSpace>(render: &Rgb32FImage) -> Rgb32FImage {
+    let mut converted = render.clone();
+    for pixel in converted.pixels_mut() {
+        *pixel = Color::<Linear>::from(Vec3::from(&*pixel)).convert::<Space>().to_rgb_f32();
+    }
+    converted
+}
+
+fn save_hdr(render: &Rgb32FImage, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let pixels: Vec<_> = render.pixels().copied().collect();
+    HdrEncoder::new(BufWriter::new(file))
+        .encode(&pixels, render.width() as usize, render.height() as usize)
+        .map_err(|error| error.to_string())
+}
+
+/// Portable float map: a small text header followed by little-endian floats, bottom row first
+fn save_pfm(render: &Rgb32FImage, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let mut writer = BufWriter::new(file);
+    // A negative scale marks the data as little-endian
+    write!(writer, "PF\n{} {}\n-1.0\n", render.width(), render.height()).map_err(|error| error.to_string())?;
+    for row in render.rows().rev() {
+        for pixel in row {
+            for channel in pixel.0 {
+                writer.write_all(&channel.to_le_bytes()).map_err(|error| error.to_string())?;
+            }
+        }
+    }
+    writer.flush().map_err(|error| error.to_string())
+}
@@ -0,0 +1,182 @@
+use std::{f64::consts::PI, str::FromStr};
+
+/// Weighs how much a sample contributes to the pixels around it
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// In pixels, samples further away than this from a pixel center do not contribute to that pixel
+    pub radius: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    /// Every sample counts fully for the pixels in its radius, with radius 0.5 only for its own pixel
+    Box,
+    Gaussian,
+    /// Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    /// Windowed sinc with as many lobes as the radius
+    Lanczos,
+    BlackmanHarris,
+}
+
+/// Smaller radii leave parts of every pixel where samples have no weight, so pixels can end up with none at all
+const MIN_FILTER_RADIUS: f64 = 0.5;
+/// Every sample is splatted to all pixels in its radius, so the cost grows with its square
+const MAX_FILTER_RADIUS: f64 = 8.;
+
+impl Default for Filter {
+    fn default() -> Self {
+        FilterKind::Box.with_default_radius()
+    }
+}
+
+impl FilterKind {
+    pub fn with_default_radius(self) -> Filter {
+        let radius = match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Gaussian | FilterKind::BlackmanHarris => 1.5,
+            FilterKind::Mitchell | FilterKind::Lanczos => 2.,
+        };
+        Filter { kind: self, radius }
+    }
+}
+
+impl Filter {
+    /// Weight of a sample at offset (x, y) from a pixel center
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    /// Number of pixels on each side of a pixel that its samples can reach
+    pub fn pixel_reach(&self) -> usize {
+        ((self.radius + 0.5).ceil() as usize).saturating_sub(1)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        let radius = self.radius;
+        if x >= radius {
+            return 0.;
+        }
+        match self.kind {
+            FilterKind::Box => 1.,
+            FilterKind::Gaussian => {
+                // Shifted down so it reaches zero at the radius
+                let sigma = radius / 3.;
+                let gaussian = |x: f64| (-x * x / (2. * sigma * sigma)).exp();
+                gaussian(x) - gaussian(radius)
+            },
+            FilterKind::Mitchell => {
+                let (b, c) = (1. / 3., 1. / 3.);
+                let x = 2. * x / radius;
+                if x < 1. {
+                    ((12. - 9. * b - 6. * c) * x.powi(3) + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b)) / 6.
+                } else {
+                    ((-b - 6. * c) * x.powi(3) + (6. * b + 30. * c) * x * x + (-12. * b - 48. * c) * x
+                        + (8. * b + 24. * c)) / 6.
+                }
+            },
+            FilterKind::Lanczos => sinc(PI * x) * sinc(PI * x / radius),
+            FilterKind::BlackmanHarris => {
+                let t = (x + radius) / (2. * radius);
+                0.35875 - 0.48829 * (2. * PI * t).cos() + 0.14128 * (4. * PI * t).cos()
+                    - 0.01168 * (6. * PI * t).cos()
+            },
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.
+    } else {
+        x.sin() / x
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parses `box`, `gaussian`, `mitchell`, `lanczos` or `blackman-harris`, optionally followed by `:RADIUS`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (name, radius) = input.split_once(':').unwrap_or((input, ""));
+        let kind = match name.to_lowercase().replace('_', "-").as_str() {
+            "box" => FilterKind::Box,
+            "gaussian" => FilterKind::Gaussian,
+            "mitchell" => FilterKind::Mitchell,
+            "lanczos" => FilterKind::Lanczos,
+            "blackman-harris" => FilterKind::BlackmanHarris,
+            _ => return Err(format!(
+                "Unknown filter '{}', expected box, gaussian, mitchell, lanczos or blackman-harris",
+                input
+            )),
+        };
+        let mut filter = kind.with_default_radius();
+        if !radius.is_empty() {
+            filter.radius = match radius.parse::<f64>() {
+                Ok(radius) if (MIN_FILTER_RADIUS..=MAX_FILTER_RADIUS).contains(&radius) => radius,
+                _ => return Err(format!(
+                    "Invalid filter radius '{}', expected {} to {} pixels",
+                    radius, MIN_FILTER_RADIUS, MAX_FILTER_RADIUS
+                )),
+            };
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, FilterKind};
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+        FilterKind::BlackmanHarris,
+    ];
+
+    #[test]
+    fn weights_end_at_the_radius() {
+        for kind in KINDS {
+            let filter = kind.with_default_radius();
+            assert!(filter.evaluate(0., 0.) > 0., "{:?} has no weight at the center", kind);
+            assert_eq!(filter.evaluate(filter.radius, 0.), 0.);
+            assert_eq!(filter.evaluate(0., -filter.radius), 0.);
+            assert_eq!(filter.evaluate(0.3, -0.2), filter.evaluate(-0.3, 0.2));
+        }
+    }
+
+    #[test]
+    fn weights_fall_off_from_the_center() {
+        for kind in [FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos, FilterKind::BlackmanHarris] {
+            let filter = kind.with_default_radius();
+            assert!(filter.evaluate(0., 0.) > filter.evaluate(0.5, 0.), "{:?} does not fall off", kind);
+        }
+        assert_eq!(FilterKind::Box.with_default_radius().evaluate(0.49, 0.49), 1.);
+    }
+
+    #[test]
+    fn pixel_reach() {
+        let reach = |radius| Filter { kind: FilterKind::Gaussian, radius }.pixel_reach();
+        assert_eq!(reach(0.5), 0);
+        assert_eq!(reach(0.51), 1);
+        assert_eq!(reach(1.5), 1);
+        assert_eq!(reach(2.), 2);
+        assert_eq!(reach(8.), 8);
+    }
+
+    #[test]
+    fn parses_radius_within_bounds() {
+        let filter: Filter = "lanczos:3".parse().unwrap();
+        assert_eq!(filter.kind, FilterKind::Lanczos);
+        assert_eq!(filter.radius, 3.);
+        assert_eq!("Blackman_Harris".parse::<Filter>().unwrap().radius, 1.5);
+        for radius in ["0", "0.4", "-1", "8.5", "1e300", "inf", "NaN", "wide"] {
+            assert!(format!("box:{}", radius).parse::<Filter>().is_err(), "radius {} was accepted", radius);
+        }
+        assert!("sinc".parse::<Filter>().is_err());
+    }
+}
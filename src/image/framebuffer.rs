@@ -0,0 +1,153 @@
+use image::Rgb32FImage;
+
+use crate::algebra::color::Color;
+
+use super::{image_chunk::ImageChunk, pixel_coordinate::PixelCoordinate};
+
+/// Below this mean luminance the error of a pixel is compared to this value instead,
+/// otherwise nearly black pixels would never converge
+const MIN_LUMINANCE: f64 = 0.05;
+
+/// Running sums of the filtered samples and their weights for every pixel,
+/// so more sample passes can be added to an existing render.
+/// The unfiltered samples taken in each pixel are summed and counted as well, with their squared luminances,
+/// to estimate how noisy each pixel still is.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    weighted_sums: Vec<Color>,
+    weights: Vec<f64>,
+    sums: Vec<Color>,
+    luminance_squares: Vec<f64>,
+    sample_counts: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            weighted_sums: vec![Color::BLACK; size],
+            weights: vec![0.; size],
+            sums: vec![Color::BLACK; size],
+            luminance_squares: vec![0.; size],
+            sample_counts: vec![0; size],
+        }
+    }
+
+    pub fn resolution(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Adds the samples of the chunk, including its contributions to the pixels of neighbouring chunks
+    pub fn add_chunk(&mut self, chunk: &ImageChunk) {
+        for (coordinate, color, weight) in chunk.splats() {
+            if coordinate.x >= self.width as usize || coordinate.y >= self.height as usize {
+                continue;
+            }
+            let index = self.index(coordinate);
+            self.weighted_sums[index] += color;
+            self.weights[index] += weight;
+        }
+        for (coordinate, color) in chunk.iter() {
+            let index = self.index(coordinate);
+            self.sums[index] += color;
+            self.luminance_squares[index] += color.luminance().powi(2);
+            self.sample_counts[index] += 1;
+        }
+    }
+
+    pub fn sample_count(&self, pixel: PixelCoordinate) -> u32 {
+        self.sample_counts[self.index(pixel)]
+    }
+
+    /// Average number of samples per pixel
+    pub fn average_samples(&self) -> f64 {
+        self.sample_counts.iter().map(|&count| count as f64).sum::<f64>() / self.sample_counts.len().max(1) as f64
+    }
+
+    /// Standard error of the mean luminance of the pixel, relative to that mean.
+    /// Infinite while there are too few samples to tell.
+    pub fn relative_error(&self, pixel: PixelCoordinate) -> f64 {
+        let index = self.index(pixel);
+        let count = self.sample_counts[index] as f64;
+        if count < 2. {
+            return f64::INFINITY;
+        }
+        let mean = self.sums[index].luminance() / count;
+        let variance = ((self.luminance_squares[index] / count - mean * mean) * count / (count - 1.)).max(0.);
+        (variance / count).sqrt() / mean.max(MIN_LUMINANCE)
+    }
+
+    /// The filtered average of the samples around every pixel, black where there are none yet
+    pub fn to_image(&self) -> Rgb32FImage {
+        let mut image = Rgb32FImage::new(self.width, self.height);
+        for ((pixel, sum), &weight) in image.pixels_mut().zip(&self.weighted_sums).zip(&self.weights) {
+            if weight > 0. {
+                *pixel = (*sum / weight).to_rgb_f32();
+            }
+        }
+        image
+    }
+
+    pub fn clear(&mut self) {
+        self.weighted_sums.fill(Color::BLACK);
+        self.weights.fill(0.);
+        self.sums.fill(Color::BLACK);
+        self.luminance_squares.fill(0.);
+        self.sample_counts.fill(0);
+    }
+
+    fn index(&self, pixel: PixelCoordinate) -> usize {
+        pixel.x + pixel.y * self.width as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Framebuffer;
+    use crate::algebra::vec3::Vec3;
+    use crate::image::{filter::Filter, image_chunk::ImageChunk};
+
+    /// A 2x1 framebuffer with the gray samples added to its first pixel, one pass each
+    fn framebuffer_with(samples: &[f64]) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        for &value in samples {
+            let mut chunk = ImageChunk::new((0, 0).into(), (1, 1).into(), Filter::default());
+            chunk.add_sample((0, 0).into(), (0., 0.), Vec3::new(value, value, value).into());
+            framebuffer.add_chunk(&chunk);
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn error_is_unknown_below_two_samples() {
+        assert_eq!(framebuffer_with(&[]).relative_error((0, 0).into()), f64::INFINITY);
+        assert_eq!(framebuffer_with(&[0.5]).relative_error((0, 0).into()), f64::INFINITY);
+        assert_eq!(framebuffer_with(&[0.5, 0.5]).relative_error((1, 0).into()), f64::INFINITY);
+    }
+
+    #[test]
+    fn identical_samples_have_no_error() {
+        assert!(framebuffer_with(&[0.3; 4]).relative_error((0, 0).into()) < 1e-6);
+    }
+
+    #[test]
+    fn error_is_the_standard_error_over_the_mean() {
+        // Mean 1, unbiased variance 0.5, standard error sqrt(0.5 / 2)
+        assert!((framebuffer_with(&[0.5, 1.5]).relative_error((0, 0).into()) - 0.5).abs() < 1e-9);
+        // Mean 0.01 is below the minimum luminance, standard error 0.01
+        assert!((framebuffer_with(&[0., 0.02]).relative_error((0, 0).into()) - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn samples_are_counted_and_cleared() {
+        let mut framebuffer = framebuffer_with(&[1., 2., 3.]);
+        assert_eq!(framebuffer.sample_count((0, 0).into()), 3);
+        assert_eq!(framebuffer.average_samples(), 1.5);
+        framebuffer.clear();
+        assert_eq!(framebuffer.sample_count((0, 0).into()), 0);
+        assert_eq!(framebuffer.relative_error((0, 0).into()), f64::INFINITY);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::algebra::color::Color;
 
-use super::pixel_coordinate::PixelCoordinate;
+use super::{filter::Filter, pixel_coordinate::PixelCoordinate};
 
 pub struct ImageChunkCoordinates{
     pub top_left : PixelCoordinate,
@@ -19,31 +19,84 @@ impl ImageChunkCoordinates{
         pixels
     }
 
-    pub fn instantiate_chunk(&self) -> ImageChunk{
-        ImageChunk::new(self.top_left, self.size)
+    pub fn instantiate_chunk(&self, filter: Filter) -> ImageChunk{
+        ImageChunk::new(self.top_left, self.size, filter)
     }
 }
 
+/// Samples for the pixels of a chunk, and those samples splatted through the reconstruction filter.
+/// The filtered part reaches past the chunk by the reach of the filter,
+/// where it overlaps with the neighbouring chunks.
 pub struct ImageChunk{
     pub top_left : PixelCoordinate,
     pub size : PixelCoordinate,
-    data : Vec<Color>,
+    filter : Filter,
+    /// Extra pixels on each side of the filtered part
+    border : usize,
+    /// The unfiltered sample of every pixel that was sampled
+    samples : Vec<Option<Color>>,
+    /// Weighted sum of the samples and sum of the weights, for the chunk with its border
+    splats : Vec<(Color, f64)>,
 }
 
 impl ImageChunk{
-    pub fn new(top_left: PixelCoordinate, size: PixelCoordinate) -> Self{
-        Self{ top_left, size, data: vec![Color::BLACK;size.x*size.y] }
+    pub fn new(top_left: PixelCoordinate, size: PixelCoordinate, filter: Filter) -> Self{
+        let border = filter.pixel_reach();
+        let splat_count = (size.x + 2 * border) * (size.y + 2 * border);
+        Self{
+            top_left,
+            size,
+            filter,
+            border,
+            samples: vec![None;size.x*size.y],
+            splats: vec![(Color::BLACK, 0.);splat_count],
+        }
     }
-    pub fn set(&mut self, pixel: PixelCoordinate, color: Color){
-        self.data[(pixel.x - self.top_left.x) + (pixel.y - self.top_left.y) * self.size.x] = color;
+
+    /// Adds the sample taken at `offset` from the center of `pixel`
+    pub fn add_sample(&mut self, pixel: PixelCoordinate, offset: (f64, f64), color: Color){
+        self.samples[(pixel.x - self.top_left.x) + (pixel.y - self.top_left.y) * self.size.x] = Some(color);
+
+        let width = self.size.x + 2 * self.border;
+        let reach = self.filter.pixel_reach() as isize;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let weight = self.filter.evaluate(dx as f64 - offset.0, dy as f64 - offset.1);
+                if weight == 0. {
+                    continue;
+                }
+                // Position within the chunk including its border
+                let x = (pixel.x - self.top_left.x + self.border) as isize + dx;
+                let y = (pixel.y - self.top_left.y + self.border) as isize + dy;
+                let splat = &mut self.splats[x as usize + y as usize * width];
+                splat.0 += color * weight;
+                splat.1 += weight;
+            }
+        }
     }
 
+    /// The unfiltered samples with their pixel
     pub fn iter(&self) -> impl Iterator<Item = (PixelCoordinate, Color)> + '_{
-        self.data
+        self.samples
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &color)| {
+                Some(((self.top_left.x + index % self.size.x, self.top_left.y + index / self.size.x).into(), color?))
+            })
+    }
+
+    /// Weighted sums of samples and their weights for the pixels the chunk contributes to.
+    /// Pixels of the border left or above the image are left out, the ones right or below it are not.
+    pub fn splats(&self) -> impl Iterator<Item = (PixelCoordinate, Color, f64)> + '_{
+        let width = self.size.x + 2 * self.border;
+        self.splats
             .iter()
             .enumerate()
-            .map(|(index, &color)| {
-                ((self.top_left.x + index % self.size.x, self.top_left.y + index / self.size.x).into(), color)
+            .filter(|(_, &(_, weight))| weight != 0.)
+            .filter_map(move |(index, &(color, weight))| {
+                let x = (self.top_left.x + index % width).checked_sub(self.border)?;
+                let y = (self.top_left.y + index / width).checked_sub(self.border)?;
+                Some(((x, y).into(), color, weight))
             })
     }
 }
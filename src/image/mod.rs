@@ -1,17 +1,20 @@
 use std::iter::once;
 
-use image::RgbImage;
-
 use self::{image_chunk::ImageChunkCoordinates, pixel_coordinate::PixelCoordinate};
 
+pub mod display;
+pub mod export;
+pub mod filter;
+pub mod framebuffer;
 pub mod image_chunk;
 pub mod pixel_coordinate;
+pub mod tone_mapping;
 
-pub fn get_chunks_iter(image : &RgbImage, chunk_size: usize) -> impl Iterator<Item = ImageChunkCoordinates>{
-    let num_x_chunks = image.width() as usize / chunk_size;
-    let num_y_chunks = image.height() as usize / chunk_size;
-    let width = image.width() as usize;
-    let height = image.height() as usize;
+pub fn get_chunks_iter((width, height) : (u32, u32), chunk_size: usize) -> impl Iterator<Item = ImageChunkCoordinates>{
+    let width = width as usize;
+    let height = height as usize;
+    let num_x_chunks = width / chunk_size;
+    let num_y_chunks = height / chunk_size;
 
     let size = PixelCoordinate::from((chunk_size, chunk_size));
 
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct PixelCoordinate{
     pub x : usize,
     pub y : usize,
@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use crate::algebra::{color::REC_709_LUMINANCE, matrix3::Matrix3, vec3::Vec3};
+
+/// Maps linear scene radiance into the 0 - 1 display range
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ToneMapping {
+    /// Values above 1 are clipped
+    #[default]
+    Clamp,
+    Reinhard,
+    /// Reinhard that reaches white at `white_point` instead of at infinity
+    ExtendedReinhard { white_point: f64 },
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms
+    Aces,
+    /// Minimal AgX, see https://iolite-engine.com/blog_posts/minimal_agx_implementation
+    AgX,
+}
+
+const ACES_INPUT: Matrix3 = Matrix3([
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+]);
+const ACES_OUTPUT: Matrix3 = Matrix3([
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+]);
+
+const AGX_INSET: Matrix3 = Matrix3([
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+]);
+const AGX_OUTSET: Matrix3 = Matrix3([
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+]);
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+impl ToneMapping {
+    /// Maps a linear Rec.709 color, the result is still linear
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        match self {
+            ToneMapping::Clamp => color,
+            ToneMapping::Reinhard => {
+                let luminance = color.dot(&REC_709_LUMINANCE);
+                color / (1. + luminance)
+            }
+            ToneMapping::ExtendedReinhard { white_point } => {
+                let luminance = color.dot(&REC_709_LUMINANCE);
+                color * (1. + luminance / (white_point * white_point)) / (1. + luminance)
+            }
+            ToneMapping::Aces => {
+                let color = ACES_INPUT * color;
+                let fitted = Vec3::new(
+                    aces_rrt_odt_fit(color.x),
+                    aces_rrt_odt_fit(color.y),
+                    aces_rrt_odt_fit(color.z),
+                );
+                (ACES_OUTPUT * fitted).ew_max(&Vec3::ZEROS).ew_min(&Vec3::ONES)
+            }
+            ToneMapping::AgX => {
+                let color = AGX_INSET * color.ew_max(&Vec3::ZEROS);
+                let encoded = Vec3::new(agx_curve(color.x), agx_curve(color.y), agx_curve(color.z));
+                // The curve output is display encoded, undo its 2.2 gamma to stay linear
+                let color = (AGX_OUTSET * encoded).ew_max(&Vec3::ZEROS).ew_min(&Vec3::ONES);
+                Vec3::new(color.x.powf(2.2), color.y.powf(2.2), color.z.powf(2.2))
+            }
+        }
+    }
+}
+
+fn aces_rrt_odt_fit(value: f64) -> f64 {
+    let a = value * (value + 0.0245786) - 0.000090537;
+    let b = value * (0.983729 * value + 0.4329510) + 0.238081;
+    a / b
+}
+
+/// Log2 encoding followed by a polynomial fit of the AgX sigmoid
+fn agx_curve(value: f64) -> f64 {
+    let x = (value.log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+}
+
+impl FromStr for ToneMapping {
+    type Err = String;
+
+    /// Parses `clamp`, `reinhard`, `extended-reinhard[:WHITE]`, `aces` or `agx`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = input.split_once(':').unwrap_or((input, ""));
+        match (name.to_lowercase().replace('_', "-").as_str(), parameter) {
+            ("clamp" | "none", "") => Ok(ToneMapping::Clamp),
+            ("reinhard", "") => Ok(ToneMapping::Reinhard),
+            ("extended-reinhard", "") => Ok(ToneMapping::ExtendedReinhard { white_point: 4. }),
+            ("extended-reinhard", white_point) => match white_point.parse::<f64>() {
+                Ok(parsed) if parsed > 0. && parsed.is_finite() => Ok(ToneMapping::ExtendedReinhard { white_point: parsed }),
+                _ => Err(format!("Invalid white point '{}'", white_point)),
+            },
+            ("aces", "") => Ok(ToneMapping::Aces),
+            ("agx", "") => Ok(ToneMapping::AgX),
+            _ => Err(format!(
+                "Unknown tone mapping '{}', expected clamp, reinhard, extended-reinhard[:WHITE], aces or agx",
+                input
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ToneMapping;
+    use crate::algebra::vec3::Vec3;
+
+    fn gray(value: f64) -> Vec3 {
+        Vec3::new(value, value, value)
+    }
+
+    #[test]
+    fn clamp_leaves_colors_unchanged() {
+        let color = Vec3::new(0.2, 1.5, 30.);
+        let mapped = ToneMapping::Clamp.apply(color);
+        assert_eq!((mapped.x, mapped.y, mapped.z), (color.x, color.y, color.z));
+    }
+
+    #[test]
+    fn reinhard_stays_below_white() {
+        for value in [0.5, 1., 100., 1e6] {
+            assert!(ToneMapping::Reinhard.apply(gray(value)).x < 1.);
+        }
+        assert!((ToneMapping::Reinhard.apply(gray(1.)).x - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn extended_reinhard_reaches_white_at_the_white_point() {
+        let tone_mapping = ToneMapping::ExtendedReinhard { white_point: 4. };
+        assert!((tone_mapping.apply(gray(4.)).x - 1.).abs() < 1e-9);
+        assert!(tone_mapping.apply(gray(2.)).x < 1.);
+    }
+
+    #[test]
+    fn filmic_curves_stay_in_range_and_increase() {
+        for tone_mapping in [ToneMapping::Aces, ToneMapping::AgX] {
+            let mut previous = -1.;
+            for step in 0..=40 {
+                let value = 2f64.powf(step as f64 / 2. - 10.);
+                let mapped = tone_mapping.apply(gray(value)).x;
+                assert!((0. ..=1.).contains(&mapped), "{:?} mapped {} to {}", tone_mapping, value, mapped);
+                assert!(mapped >= previous, "{:?} decreases at {}", tone_mapping, value);
+                previous = mapped;
+            }
+            for color in [Vec3::new(50., 0., 0.), Vec3::new(0., 0.3, 8.), Vec3::new(0., 0., 0.)] {
+                let mapped = tone_mapping.apply(color);
+                for channel in [mapped.x, mapped.y, mapped.z] {
+                    assert!((0. ..=1.).contains(&channel), "{:?} mapped {:?} to {:?}", tone_mapping, color, mapped);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parses_names_and_white_points() {
+        assert!(matches!("AgX".parse(), Ok(ToneMapping::AgX)));
+        assert!(matches!(
+            "extended_reinhard:2".parse(),
+            Ok(ToneMapping::ExtendedReinhard { white_point }) if white_point == 2.
+        ));
+        for input in ["extended-reinhard:0", "extended-reinhard:-1", "extended-reinhard:inf", "aces:2", "filmic"] {
+            assert!(input.parse::<ToneMapping>().is_err(), "{}", input);
+        }
+    }
+}
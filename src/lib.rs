@@ -0,0 +1,9 @@
+pub mod algebra;
+pub mod hit;
+pub mod material;
+pub mod renderer;
+pub mod scene;
+pub mod world;
+pub mod image;
+
+pub use crate::algebra::vec3::Vec3;
@@ -1,70 +1,150 @@
-mod algebra;
-mod hit;
-mod material;
-mod renderer;
-mod world;
-pub mod image;
-
-use show_image::{create_window, AsImageView, WindowOptions, event};
-use ::image::RgbImage;
-use std::f64::consts::PI;
-
-use crate::algebra::vec3::Vec3;
-use crate::renderer::Renderer;
-use crate::world::camera::Camera;
-use crate::world::World;
-
-// const WIDTH: u32 = 1920;
-// const HEIGHT: u32 = 1080;
-const WIDTH: u32 = 600;
-const HEIGHT: u32 = 400;
-#[show_image::main]
+mod cli;
+
+use std::sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc};
+use std::thread;
+
+use show_image::{create_window, AsImageView, WindowOptions, WindowProxy, event::{self, VirtualKeyCode}};
+use image::Rgb32FImage;
+
+use raytracer::image::{display::to_display_image, export::save_render, framebuffer::Framebuffer};
+use raytracer::renderer::{render_settings::RenderSettings, Renderer};
+use raytracer::scene::Scene;
+
+use crate::cli::Arguments;
+
 fn main() {
-    let mut image = RgbImage::new(WIDTH, HEIGHT);
-    let mut camera = Camera::new(PI / 4., &image);
-    camera.position = Vec3::new(10., 5., 10.);
-    camera.look_at(Vec3::new(0., 0.1, 0.));
+    let arguments = match cli::parse(std::env::args().skip(1)) {
+        Ok(Some(arguments)) => arguments,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let mut world = World::with_camera(camera);
-    world.import_3d_file("models/medieval_house.obj").unwrap();
-    world.import_material_file("models/medieval_house.mtl").unwrap();
-    world.import_skybox_file("images/above_clouds.jpg").unwrap();
+    // The window context needs a display, so only start it when a window is requested
+    if arguments.no_window {
+        render_without_window(&arguments);
+    } else {
+        show_image::run_context(move || render_in_window(&arguments));
+    }
+}
+
+fn render_without_window(arguments: &Arguments) {
+    let scene = load_scene_or_exit(arguments);
+
+    match &arguments.output {
+        Some(output) => match scene.render_to_file(output) {
+            Ok(()) => println!("Saved render to {}", output),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            println!("No --output given, the render will not be saved.");
+            scene.render();
+        }
+    }
+}
+
+fn render_in_window(arguments: &Arguments) {
+    let Scene { world, mut settings } = load_scene_or_exit(arguments);
+    let (width, height) = world.camera.resolution();
+    let mut framebuffer = Framebuffer::new(width, height);
 
     let mut renderer = Renderer::default();
 
-    let window = make_window();
+    let window = make_window(width, height);
+    let stop = Arc::new(AtomicBool::new(false));
+    let key_presses = listen_for_keys(&window, stop.clone());
 
-    renderer.render(&world, &mut image, 5, 4);
+    let extra_samples = settings.max_samples;
+    let show = |framebuffer: &Framebuffer| {
+        let image = to_display_image(&framebuffer.to_image(), settings.tone_mapping, settings.exposure);
+        window
+            .set_image("render", image.as_image_view().unwrap())
+            .unwrap();
+    };
 
-    window
-        .set_image("render", image.as_image_view().unwrap())
-        .unwrap();
+    renderer.render_progressive(&world, &mut framebuffer, &settings, &stop, show);
+    save_output(arguments, &framebuffer.to_image(), &settings);
 
-    for event in window.event_channel().unwrap() {
-        if let event::WindowEvent::KeyboardInput(event) = event {
-            if event.input.key_code == Some(event::VirtualKeyCode::Escape)
-                && event.input.state.is_pressed()
-            {
-                break;
-            }
-            if event.input.key_code == Some(event::VirtualKeyCode::R)
-            && event.input.state.is_pressed()
-            {
-                renderer.render(&world, &mut image, 1, 3);
-                window
-                .set_image("render", image.as_image_view().unwrap())
-                .unwrap();
+    for key in key_presses {
+        match key {
+            VirtualKeyCode::Escape => break,
+            VirtualKeyCode::R => {
+                // Adds samples to the current image, with a stricter threshold so converged pixels get some too
+                let max_samples = settings.max_samples + extra_samples;
+                if let Err(error) = settings.sampler.check_sample_count(max_samples, (width, height)) {
+                    eprintln!("{}", error);
+                    continue;
+                }
+                settings.sampler_budget.get_or_insert(settings.max_samples);
+                settings.max_samples = max_samples;
+                settings.noise_threshold /= 2.;
+                stop.store(false, Ordering::Relaxed);
+                renderer.render_progressive(&world, &mut framebuffer, &settings, &stop, show);
+                save_output(arguments, &framebuffer.to_image(), &settings);
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Forwards key presses from the window. Space or Escape, or closing the window, stops a running render.
+fn listen_for_keys(window: &WindowProxy, stop: Arc<AtomicBool>) -> mpsc::Receiver<VirtualKeyCode> {
+    let events = window.event_channel().unwrap();
+    let (key_sender, key_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events {
+            if let event::WindowEvent::KeyboardInput(event) = event {
+                if !event.input.state.is_pressed() {
+                    continue;
+                }
+                if let Some(key) = event.input.key_code {
+                    if matches!(key, VirtualKeyCode::Space | VirtualKeyCode::Escape) {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    if key_sender.send(key).is_err() {
+                        break;
+                    }
+                }
             }
         }
+        stop.store(true, Ordering::Relaxed);
+    });
+    key_receiver
+}
+
+fn load_scene_or_exit(arguments: &Arguments) -> Scene {
+    match arguments.load_scene() {
+        Ok(scene) => scene,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
     }
 }
 
-fn make_window() -> show_image::WindowProxy{
+fn save_output(arguments: &Arguments, image: &Rgb32FImage, settings: &RenderSettings) {
+    if let Some(output) = &arguments.output {
+        match save_render(image, output, settings.tone_mapping, settings.exposure, settings.color_space) {
+            Ok(()) => println!("Saved render to {}", output),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+}
+
+fn make_window(width: u32, height: u32) -> WindowProxy{
     create_window(
         "image",
         WindowOptions::new()
-            .set_size(Some([WIDTH, HEIGHT]))
+            .set_size(Some([width, height]))
             .set_default_controls(true),
     )
     .unwrap()
-}
\ No newline at end of file
+}
@@ -15,6 +15,17 @@ pub enum LumaMap{
     Texture(ImageBuffer<Luma<f32>, Vec<f32>>)
 }
 
+impl RgbMap{
+    /// The value at texture coordinates (u, v) of a surface, repeating the texture outside of 0 - 1.
+    /// v goes up the image, as in .obj files.
+    pub fn surface_value_at(&self, (u, v): (f64, f64)) -> Vec3 {
+        match self {
+            RgbMap::Color(color) => *color,
+            RgbMap::Texture(texture) => texture.get_value_at(u.rem_euclid(1.), 1. - v.rem_euclid(1.)),
+        }
+    }
+}
+
 pub trait GetValueAt<T>{
     fn get_value_at(&self, u: f64, v: f64) -> T;
 }
@@ -2,11 +2,12 @@ pub mod map;
 
 use crate::Vec3;
 
-// use self::map::RgbMap;
+use self::map::RgbMap;
 
 #[derive(Debug)]
 pub struct Material {
-    pub diffuse_color: Vec3,
+    /// A color, or a texture looked up at the texture coordinates of the hit
+    pub diffuse_color: RgbMap,
     pub luminance: Vec3,
     pub refraction: f64,
     pub ior: f64,
@@ -19,7 +20,7 @@ pub struct Material {
 impl Material {
     pub fn new(color: Vec3) -> Self {
         Material {
-            diffuse_color: color,
+            diffuse_color: color.into(),
             specular_color: Vec3::ONES,
             luminance: Vec3::new(0., 0., 0.),
             refraction: 0.,
@@ -31,7 +32,7 @@ impl Material {
 
     pub fn base_diffuse() -> Self {
         Material {
-            diffuse_color: Vec3::new(0.5, 0.5, 0.5),
+            diffuse_color: Vec3::new(0.5, 0.5, 0.5).into(),
             specular_color: Vec3::ONES,
             luminance: Vec3::new(0., 0., 0.),
             refraction: 0.,
@@ -42,7 +43,7 @@ impl Material {
     }
     pub fn as_light(luminance: Vec3) -> Self {
         Material {
-            diffuse_color: Vec3::new(0., 0., 0.),
+            diffuse_color: Vec3::new(0., 0., 0.).into(),
             specular_color: Vec3::ZEROS,
             luminance,
             refraction: 0.,
@@ -1,45 +1,63 @@
 use crate::algebra::color::Color;
 use crate::algebra::ray::Ray;
+use crate::algebra::vec3::Vec3;
 use crate::image::image_chunk::{ImageChunkCoordinates, ImageChunk};
+use crate::image::pixel_coordinate::PixelCoordinate;
 use crate::renderer::ray_instancer::RayInstancer;
 use crate::renderer::Tracer;
+use super::render_settings::RenderSettings;
+use super::sampler::Sampler;
 use super::shader::Shader;
-use super::shader::shade_package::ShadePackage;
 
+/// Bounces every path takes before Russian roulette may end it
+const ROULETTE_START: u8 = 3;
 
-pub fn trace_chunk(chunk_coordinates: ImageChunkCoordinates, ray_instancer: &RayInstancer, tracer : &Tracer, shader: &Shader, max_bounces : u8) -> ImageChunk{
-    let mut result = chunk_coordinates.instantiate_chunk();
-    for pixel in chunk_coordinates.pixels(){
-        let start_rays = ray_instancer.rays_for_pixel(pixel);
-        let mut accumulated_color = Color::BLACK;
-        for ray in &start_rays{
-            accumulated_color += process_ray(ray, tracer, shader, max_bounces);
-        }
-        result.set(pixel, accumulated_color / start_rays.len() as f64);
+
+/// Traces one sample for each of the given pixels of the chunk, with the sample index of that pixel
+pub fn trace_chunk(chunk_coordinates: &ImageChunkCoordinates, pixels: Vec<(PixelCoordinate, u32)>, sampler: &mut dyn Sampler, ray_instancer: &RayInstancer, tracer : &Tracer, shader: &Shader, settings: &RenderSettings) -> ImageChunk{
+    let mut result = chunk_coordinates.instantiate_chunk(settings.filter);
+    for (pixel, sample_index) in pixels{
+        sampler.start_pixel_sample(pixel, sample_index);
+        let (u, v) = sampler.get_2d();
+        let offset = (u - 0.5, v - 0.5);
+        let ray = ray_instancer.ray_for_pixel(pixel, offset);
+        result.add_sample(pixel, offset, trace_path(ray, tracer, shader, sampler, settings.max_bounces));
     }
     result
 }
 
-fn process_ray(ray: &Ray, tracer: &Tracer, shader: &Shader, remaining_bounces: u8) -> Color {
-    if remaining_bounces == 0 {
-        return Color::BLACK;
-    }
+/// Follows a single path from the camera, adding the light found at each surface times the fraction of it
+/// that makes its way back along the path
+fn trace_path(mut ray: Ray, tracer: &Tracer, shader: &Shader, sampler: &mut dyn Sampler, max_bounces: u8) -> Color {
+    let mut light = Vec3::ZEROS;
+    let mut throughput = Vec3::ONES;
+    let mut bsdf_pdf = None;
+
+    for bounce in 0..max_bounces {
+        let trace_result = tracer.trace_ray(&ray);
+        let continues = bounce + 1 < max_bounces;
+        let shade_result = shader.shade_hit(&trace_result, &ray, bsdf_pdf, continues, tracer, sampler);
+        light += throughput * shade_result.light;
+
+        let Some(next) = shade_result.next else {
+            break;
+        };
+        throughput *= next.multiplier;
+        if throughput.max() <= 0. {
+            break;
+        }
+
+        // Ends paths carrying little light at random, and gives the survivors what the others would have added
+        if bounce + 1 >= ROULETTE_START {
+            let survival = throughput.max().min(1.);
+            if sampler.get_1d() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
 
-    let trace_result = tracer.trace_ray(ray);
-    let shade_result = shader.shade_hit(&trace_result, ray);
-
-    shade_result
-    .iter()
-    .map(|shade_package| match shade_package{
-        ShadePackage::Ray(trace_package) => {
-            trace_package.multiplier *
-            process_ray(
-                &trace_package.ray,
-                tracer,
-                shader,
-                remaining_bounces - 1)
-        },
-        ShadePackage::Color(color) => *color,
-    })
-    .sum()
-}
\ No newline at end of file
+        ray = next.ray;
+        bsdf_pdf = next.bsdf_pdf;
+    }
+    light.into()
+}
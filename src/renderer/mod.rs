@@ -1,17 +1,30 @@
 mod ray_instancer;
+pub mod render_settings;
+pub mod sampler;
 pub mod shader;
 pub mod compute;
 pub mod tracer;
 
-use crossbeam_channel::bounded;
-use image::{ImageBuffer, Pixel, Rgb};
+use crossbeam_channel::unbounded;
+use image::Rgb32FImage;
 
-use std::thread::{self, available_parallelism};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+    thread::{self, available_parallelism},
+    time::{Duration, Instant},
+};
 
-use self::{shader::Shader, tracer::Tracer};
-use crate::{renderer::ray_instancer::RayInstancer, world::World, image::get_chunks_iter};
+use self::{render_settings::RenderSettings, shader::Shader, tracer::Tracer};
+use crate::{
+    renderer::ray_instancer::RayInstancer,
+    world::World,
+    image::{framebuffer::Framebuffer, get_chunks_iter, image_chunk::ImageChunkCoordinates, pixel_coordinate::PixelCoordinate},
+};
 
 const CHUNK_SIZE: usize = 16;
+/// Minimum time between two partial frames published by a progressive render
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Default)]
 pub struct Renderer<'a> {
@@ -24,80 +37,157 @@ impl<'a> Renderer<'a> {
     pub fn render(
         &mut self,
         world: &'a World,
-        image: &mut ImageBuffer<Rgb<u8>, Vec<<Rgb<u8> as Pixel>::Subpixel>>,
-        super_samples_sqrt: usize,
-        max_bounces: u8,
+        image: &mut Rgb32FImage,
+        settings: &RenderSettings,
     ) {
-        self.pre_compute(world, super_samples_sqrt);
-
-        let start_time = std::time::Instant::now();
+        let mut framebuffer = Framebuffer::new(image.width(), image.height());
+        let never_stop = AtomicBool::new(false);
+        self.render_progressive(world, &mut framebuffer, settings, &never_stop, |_| {});
+        *image = framebuffer.to_image();
+    }
 
-        let number_of_cores = available_parallelism().unwrap().get();
+    /// Adds sample passes to the framebuffer until every pixel has converged to `settings.noise_threshold`
+    /// or reached `settings.max_samples`, or until `settings.time_limit` runs out or `stop` is set.
+    /// While rendering `on_update` is called with the framebuffer, at most every `UPDATE_INTERVAL`
+    /// and once more when done.
+    /// Returns whether the render completed.
+    pub fn render_progressive(
+        &mut self,
+        world: &'a World,
+        framebuffer: &mut Framebuffer,
+        settings: &RenderSettings,
+        stop: &AtomicBool,
+        mut on_update: impl FnMut(&Framebuffer),
+    ) -> bool {
+        self.pre_compute(world);
+
+        let start_time = Instant::now();
+        let out_of_time = || {
+            settings.time_limit.is_some_and(|time_limit| start_time.elapsed() >= time_limit)
+        };
+        let should_stop = || stop.load(Ordering::Relaxed) || out_of_time();
+
+        let number_of_cores = settings
+            .threads
+            .unwrap_or_else(|| available_parallelism().unwrap().get());
         println!("Started rendering. with {}  cores.", number_of_cores);
 
-        let (result_sender, result_receiver) = bounded(100);
+        let resolution = framebuffer.resolution();
+        let chunks: Vec<ImageChunkCoordinates> = get_chunks_iter(resolution, CHUNK_SIZE).collect();
+        let mut completed = false;
+
+        let (result_sender, result_receiver) = unbounded();
         thread::scope(|s| {
-            let (task_sender, task_receiver) = bounded(100);
+            let (task_sender, task_receiver) = unbounded();
 
             let ray_instancer = &self.ray_instancer;
             let tracer = &self.tracer;
             let shader = &self.shader;
             
             // Spawn processing threads
-            for _ in 0..number_of_cores - 1 {
+            for _ in 0..number_of_cores {
                 s.spawn({
                     let task_receiver = task_receiver.clone();
                     let result_sender = result_sender.clone();
                     move || {
-                        while let Ok(chunk) = task_receiver.recv() {
+                        let samples_per_pixel = settings.sampler_budget.unwrap_or(settings.max_samples);
+                        let mut sampler = settings.sampler.create(samples_per_pixel, resolution, settings.seed);
+                        while let Ok((index, chunk, pixels)) = task_receiver.recv() {
                             let result = compute::trace_chunk(
                                 chunk,
+                                pixels,
+                                sampler.as_mut(),
                                 ray_instancer,
                                 tracer,
                                 shader,
-                                max_bounces,
+                                settings,
                             );
-                            result_sender.to_owned().send(result).unwrap();
+                            result_sender.to_owned().send((index, result)).unwrap();
                         }
                     }
                 });
             }
 
+            // Only the workers send results, so a failing render ends instead of waiting for them forever
             drop(result_sender);
 
-            let chunks = get_chunks_iter(image, CHUNK_SIZE);
+            // Every pass adds one sample to each pixel that still needs one,
+            // the results of a pass decide which pixels are sampled in the next one
+            let mut last_update = Instant::now();
+            while !should_stop() {
+                let tasks: Vec<_> = chunks
+                    .iter()
+                    .filter_map(|chunk| {
+                        let pixels: Vec<(PixelCoordinate, u32)> = chunk
+                            .pixels()
+                            .into_iter()
+                            .filter(|&pixel| needs_samples(framebuffer, pixel, settings))
+                            .map(|pixel| (pixel, framebuffer.sample_count(pixel)))
+                            .collect();
+                        (!pixels.is_empty()).then_some((chunk, pixels))
+                    })
+                    .collect();
+                if tasks.is_empty() {
+                    completed = true;
+                    break;
+                }
 
-            // Spawn result processor thread
-            s.spawn(|| {
-                while let Ok(result) = result_receiver.recv() {
-                    for (coordinate, color) in result.iter() {
-                        *image.get_pixel_mut(coordinate.x as u32, coordinate.y as u32) =
-                            color.to_srgb().clamp_to_rgb();
+                let mut pending = tasks.len();
+                for (index, (chunk, pixels)) in tasks.into_iter().enumerate() {
+                    task_sender.send((index, chunk, pixels)).unwrap();
+                }
+                // Results are merged in task order, whichever thread finishes first,
+                // so the splats overlapping between chunks always add up the same way
+                let mut finished = BTreeMap::new();
+                let mut next_to_merge = 0;
+                while pending > 0 {
+                    if should_stop() {
+                        // Tasks nobody has picked up yet will not be done anymore
+                        pending -= task_receiver.try_iter().count();
+                        if pending == 0 {
+                            break;
+                        }
+                    }
+                    let (index, result) = result_receiver.recv().unwrap();
+                    pending -= 1;
+                    finished.insert(index, result);
+                    while let Some(result) = finished.remove(&next_to_merge) {
+                        framebuffer.add_chunk(&result);
+                        next_to_merge += 1;
+                    }
+                    if last_update.elapsed() >= UPDATE_INTERVAL {
+                        on_update(framebuffer);
+                        last_update = Instant::now();
                     }
                 }
-            });
-
-            // Generate chunks
-            for chunk in chunks{
-                task_sender.send(chunk).unwrap();
+                // Only left after stopping, behind tasks that were dropped
+                for result in finished.values() {
+                    framebuffer.add_chunk(result);
+                }
             }
+
+            drop(task_sender);
         });
+        on_update(framebuffer);
 
         println!(
-            "Rendering done in in {:.2} seconds",
-            start_time.elapsed().as_secs_f32()
+            "Rendering {} in in {:.2} seconds, {:.1} samples per pixel on average",
+            if completed { "done" } else { "stopped" },
+            start_time.elapsed().as_secs_f32(),
+            framebuffer.average_samples()
         );
 
         self.clean_up();
+        completed
     }
 
-    fn pre_compute(&mut self, world: &'a World, super_samples_sqrt: usize) {
+    fn pre_compute(&mut self, world: &'a World) {
         let start_time = std::time::Instant::now();
 
         self.tracer.pre_compute(world);
         self.shader.pre_compute(world);
         self.ray_instancer
-            .pre_compute(super_samples_sqrt, world.camera);
+            .pre_compute(world.camera);
 
         println!(
             "Pre-compute done in in {:.3} seconds",
@@ -110,3 +200,9 @@ impl<'a> Renderer<'a> {
         self.shader.clear();
     }
 }
+
+fn needs_samples(framebuffer: &Framebuffer, pixel: PixelCoordinate, settings: &RenderSettings) -> bool {
+    let samples = framebuffer.sample_count(pixel) as usize;
+    samples < settings.min_samples
+        || (samples < settings.max_samples && framebuffer.relative_error(pixel) >= settings.noise_threshold)
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use crate::image::{export::OutputColorSpace, filter::Filter, tone_mapping::ToneMapping};
+
+use super::sampler::SamplerKind;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    /// Every pixel gets at least this many samples, each in its own pass over the image
+    pub min_samples: usize,
+    /// No pixel gets more samples than this, converged or not
+    pub max_samples: usize,
+    /// A pixel stops getting samples once the standard error of its luminance, relative to that luminance,
+    /// is below this. With 0 every pixel gets `max_samples`.
+    pub noise_threshold: f64,
+    /// Where in the pixel and in which directions the rays of each sample go
+    pub sampler: SamplerKind,
+    /// Samples per pixel the sampler lays out its sequences for, `max_samples` when `None`.
+    /// Samples added to a finished render keep it, so they continue the sequences of the earlier ones.
+    pub sampler_budget: Option<usize>,
+    /// How samples are weighed into the pixels around them
+    pub filter: Filter,
+    /// Renders with the same seed and settings are identical, whatever the number of threads
+    pub seed: u64,
+    /// Stops adding passes once this much time has passed
+    pub time_limit: Option<Duration>,
+    /// Surfaces a path can hit, the light of emissive materials is still sampled directly at the last one.
+    /// Paths carrying little light usually end before, by Russian roulette.
+    pub max_bounces: u8,
+    /// Number of threads to render with. Uses all available cores when `None`.
+    pub threads: Option<usize>,
+    /// Used when converting the linear render for display
+    pub tone_mapping: ToneMapping,
+    /// In stops, applied before tone mapping
+    pub exposure: f64,
+    /// The color space of saved renders, the window always shows sRGB
+    pub color_space: OutputColorSpace,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: 256,
+            noise_threshold: 0.02,
+            sampler: SamplerKind::Sobol,
+            sampler_budget: None,
+            filter: Filter::default(),
+            seed: 0,
+            time_limit: None,
+            max_bounces: 16,
+            threads: None,
+            tone_mapping: ToneMapping::Clamp,
+            exposure: 0.,
+            color_space: OutputColorSpace::Srgb,
+        }
+    }
+}
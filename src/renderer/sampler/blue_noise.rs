@@ -0,0 +1,151 @@
+use crate::image::pixel_coordinate::PixelCoordinate;
+
+use super::{hash, mix_bits, sobol::{owen_scramble, owen_scrambled_sobol_2d}, u32_to_unit, Sampler};
+
+/// All 24 orderings of a base 4 digit
+const PERMUTATIONS: [[u64; 4]; 24] = [
+    [0, 1, 2, 3], [0, 1, 3, 2], [0, 2, 1, 3], [0, 2, 3, 1], [0, 3, 2, 1], [0, 3, 1, 2],
+    [1, 0, 2, 3], [1, 0, 3, 2], [1, 2, 0, 3], [1, 2, 3, 0], [1, 3, 2, 0], [1, 3, 0, 2],
+    [2, 1, 0, 3], [2, 1, 3, 0], [2, 0, 1, 3], [2, 0, 3, 1], [2, 3, 0, 1], [2, 3, 1, 0],
+    [3, 1, 2, 0], [3, 1, 0, 2], [3, 2, 1, 0], [3, 2, 0, 1], [3, 0, 2, 1], [3, 0, 1, 2],
+];
+
+/// One Sobol sequence shared by the whole image, where neighbouring pixels in Morton order
+/// get neighbouring parts of it. The error of nearby pixels is then negatively correlated, which looks like blue noise.
+/// See Ahmed and Wonka, Screen-Space Blue-Noise Diffusion of Monte Carlo Sampling Error via Hierarchical Ordering of Pixels.
+/// Samples past the count the sampler was made for continue the sequence in the next image-sized block of it.
+pub struct BlueNoiseSampler {
+    log2_samples_per_pixel: u32,
+    log2_resolution: u32,
+    base4_digits: u32,
+    seed: u64,
+    morton_index: u64,
+    dimension: u32,
+}
+
+impl BlueNoiseSampler {
+    /// Sample indices have 32 bits, more samples than that over the whole image would share them
+    pub fn check_sample_count(samples_per_pixel: usize, resolution: (u32, u32)) -> Result<(), String> {
+        let (log2_samples_per_pixel, log2_resolution) = log2_sizes(samples_per_pixel, resolution);
+        if 2 * log2_resolution + log2_samples_per_pixel > 32 {
+            return Err(format!(
+                "The blue-noise sampler supports up to 2^32 samples per image, {}x{} at {} samples per pixel needs more. Use the sobol sampler instead",
+                resolution.0, resolution.1, samples_per_pixel
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn new(samples_per_pixel: usize, resolution: (u32, u32), seed: u64) -> Self {
+        let (log2_samples_per_pixel, log2_resolution) = log2_sizes(samples_per_pixel, resolution);
+        Self {
+            log2_samples_per_pixel,
+            log2_resolution,
+            base4_digits: log2_resolution + log2_samples_per_pixel.div_ceil(2),
+            seed,
+            morton_index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Index into the Sobol sequence for the current dimension. The base 4 digits of the Morton index
+    /// are randomly permuted, depending on the digits above them, so the pixel order has no visible structure.
+    /// Fits in 32 bits for the sizes `check_sample_count` accepts.
+    fn sample_index(&self) -> u32 {
+        // Blocks after the first are taken in order, each of them is well spread on its own
+        let block_bits = 2 * self.log2_resolution + self.log2_samples_per_pixel;
+        let block = self.morton_index >> block_bits << block_bits;
+
+        let odd_power = self.log2_samples_per_pixel & 1 == 1;
+        let last_digit = if odd_power { 1 } else { 0 };
+        let dimension_hash = 0x55555555 * self.dimension as u64;
+
+        let mut sample_index = block;
+        for digit_index in (last_digit..self.base4_digits).rev() {
+            let shift = 2 * digit_index - last_digit;
+            let digit = (self.morton_index >> shift) & 3;
+            let higher_digits = self.morton_index >> (shift + 2);
+            let permutation = (mix_bits(higher_digits ^ dimension_hash) >> 24) % 24;
+            sample_index |= PERMUTATIONS[permutation as usize][digit as usize] << shift;
+        }
+        if odd_power {
+            let digit = self.morton_index & 1;
+            sample_index |= digit ^ (mix_bits((self.morton_index >> 1) ^ dimension_hash) & 1);
+        }
+        sample_index as u32
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        let seed = hash(&[self.seed, self.dimension as u64]);
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, pixel: PixelCoordinate, index: u32) {
+        let index = index as u64;
+        let block = index >> self.log2_samples_per_pixel;
+        let pixel_index = (block << (2 * self.log2_resolution)) | morton_encode(pixel.x as u32, pixel.y as u32);
+        self.morton_index = (pixel_index << self.log2_samples_per_pixel) | (index & ((1 << self.log2_samples_per_pixel) - 1));
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let index = self.sample_index();
+        let seed = self.next_seed();
+        u32_to_unit(owen_scramble(index.reverse_bits(), seed as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let index = self.sample_index();
+        let seed = self.next_seed();
+        // Unlike the Sobol sampler the index is not shuffled, that would undo the Morton ordering
+        owen_scrambled_sobol_2d(index, seed)
+    }
+}
+
+/// Base 2 logarithms of the samples per pixel and of the image size, both rounded up to a power of two.
+/// Square Morton tiles cover the image, so the larger side counts.
+fn log2_sizes(samples_per_pixel: usize, resolution: (u32, u32)) -> (u32, u32) {
+    (
+        samples_per_pixel.max(1).next_power_of_two().trailing_zeros(),
+        resolution.0.max(resolution.1).max(1).next_power_of_two().trailing_zeros(),
+    )
+}
+
+/// Interleaves the bits of x and y
+fn morton_encode(x: u32, y: u32) -> u64 {
+    fn spread_bits(value: u32) -> u64 {
+        let mut value = value as u64;
+        value = (value | (value << 16)) & 0x0000ffff0000ffff;
+        value = (value | (value << 8)) & 0x00ff00ff00ff00ff;
+        value = (value | (value << 4)) & 0x0f0f0f0f0f0f0f0f;
+        value = (value | (value << 2)) & 0x3333333333333333;
+        value = (value | (value << 1)) & 0x5555555555555555;
+        value
+    }
+    spread_bits(x) | (spread_bits(y) << 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::BlueNoiseSampler;
+    use crate::renderer::sampler::Sampler;
+
+    #[test]
+    fn samples_past_the_budget_continue_the_sequence() {
+        let mut sampler = BlueNoiseSampler::new(4, (8, 8), 0);
+        let mut indices = HashSet::new();
+        for index in 0..12 {
+            for y in 0..8 {
+                for x in 0..8 {
+                    sampler.start_pixel_sample((x, y).into(), index);
+                    assert!(indices.insert(sampler.sample_index()), "index {} of pixel {} {} is taken", index, x, y);
+                }
+            }
+        }
+    }
+}
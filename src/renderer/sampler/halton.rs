@@ -0,0 +1,68 @@
+use crate::image::pixel_coordinate::PixelCoordinate;
+
+use super::{mix_bits, permutation_element, pixel_hash, u64_to_unit, Sampler};
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, one prime base per dimension, with the digits Owen scrambled per pixel.
+/// Dimensions past the available bases use random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: PixelCoordinate,
+    index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: PixelCoordinate::default(), index: 0, dimension: 0 }
+    }
+
+    fn next(&mut self) -> f64 {
+        let seed = pixel_hash(self.seed, self.pixel, self.dimension);
+        let value = match PRIMES.get(self.dimension as usize) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.index as u64, seed),
+            None => u64_to_unit(mix_bits(seed ^ self.index as u64)),
+        };
+        self.dimension += 1;
+        value
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: PixelCoordinate, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+/// Mirrors the digits of `index` in `base` around the decimal point,
+/// permuting each digit randomly depending on the digits before it
+fn owen_scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1. / base as f64;
+    let mut inverse_base_power = 1.;
+    let mut reversed_digits = 0u64;
+    // Continue until the digits no longer change the result
+    while 1. - (base - 1) as f64 * inverse_base_power < 1. {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit_seed = mix_bits(seed ^ reversed_digits) as u32;
+        let digit = permutation_element(digit as u32, base as u32, digit_seed) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        inverse_base_power *= inverse_base;
+        index = next;
+    }
+    (reversed_digits as f64 * inverse_base_power).min(1. - f64::EPSILON / 2.)
+}
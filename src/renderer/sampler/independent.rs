@@ -0,0 +1,30 @@
+use crate::image::pixel_coordinate::PixelCoordinate;
+
+use super::{hash, Sampler};
+
+/// Uniform random numbers, without any relation between samples.
+/// The generator is seeded for every pixel sample, so the numbers do not depend on the thread or chunk order.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: fastrand::Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: fastrand::Rng::with_seed(seed) }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: PixelCoordinate, index: u32) {
+        self.rng.seed(hash(&[self.seed, pixel.x as u64, pixel.y as u64, index as u64]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.f64(), self.rng.f64())
+    }
+}
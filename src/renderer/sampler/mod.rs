@@ -0,0 +1,234 @@
+mod blue_noise;
+mod halton;
+mod independent;
+mod sobol;
+mod stratified;
+
+use std::str::FromStr;
+
+use crate::image::pixel_coordinate::PixelCoordinate;
+
+use self::{blue_noise::BlueNoiseSampler, halton::HaltonSampler, independent::IndependentSampler, sobol::SobolSampler, stratified::StratifiedSampler};
+
+/// Provides the random numbers for one sample of a pixel, dimension by dimension.
+/// The first 2D sample is the position in the pixel, the following ones are used by the shader for its bounces.
+pub trait Sampler {
+    /// Starts sample number `index` of the pixel, restarting at the first dimension
+    fn start_pixel_sample(&mut self, pixel: PixelCoordinate, index: u32);
+    /// A number in [0, 1) for the next dimension
+    fn get_1d(&mut self) -> f64;
+    /// Two numbers in [0, 1) for the next two dimensions
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SamplerKind {
+    /// Independent uniform random numbers
+    Independent,
+    /// Jittered samples, one in each cell of a grid of about the sample count, in a random order per pixel
+    Stratified,
+    /// Halton sequence with Owen scrambled digits per pixel
+    Halton,
+    /// Owen scrambled 2D Sobol points, shuffled per pixel and dimension pair
+    #[default]
+    Sobol,
+    /// Sobol points spread over neighbouring pixels in Morton order, so the remaining error looks like blue noise
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// A sampler laid out for `samples_per_pixel` samples per pixel, samples past that continue its sequences.
+    /// Samplers with the same seed give the same numbers for the same pixel, sample index and dimension.
+    pub fn create(&self, samples_per_pixel: usize, resolution: (u32, u32), seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(samples_per_pixel, resolution, seed)),
+        }
+    }
+
+    /// Whether the sampler can give `samples_per_pixel` distinct samples to every pixel of an image of `resolution`
+    pub fn check_sample_count(&self, samples_per_pixel: usize, resolution: (u32, u32)) -> Result<(), String> {
+        match self {
+            SamplerKind::BlueNoise => BlueNoiseSampler::check_sample_count(samples_per_pixel, resolution),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    /// Parses `independent`, `stratified`, `halton`, `sobol` or `blue-noise`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().replace('_', "-").as_str() {
+            "independent" | "random" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "blue-noise" | "zsobol" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!(
+                "Unknown sampler '{}', expected independent, stratified, halton, sobol or blue-noise",
+                input
+            )),
+        }
+    }
+}
+
+/// Finalizer of MurmurHash3 / SplitMix64, scrambles all bits of the input
+fn mix_bits(mut value: u64) -> u64 {
+    value ^= value >> 31;
+    value = value.wrapping_mul(0x7fb5d329728ea185);
+    value ^= value >> 27;
+    value = value.wrapping_mul(0x81dadef4bc2dd44d);
+    value ^= value >> 33;
+    value
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0, |hash, &value| mix_bits(hash ^ value.wrapping_mul(0x9e3779b97f4a7c15)))
+}
+
+fn pixel_hash(seed: u64, pixel: PixelCoordinate, dimension: u32) -> u64 {
+    hash(&[seed, pixel.x as u64, pixel.y as u64, dimension as u64])
+}
+
+/// Maps all 32 bits to [0, 1)
+fn u32_to_unit(value: u32) -> f64 {
+    value as f64 / (1u64 << 32) as f64
+}
+
+/// Maps the upper 53 bits to [0, 1)
+fn u64_to_unit(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Element `index` of a random permutation of 0..`length` chosen by `seed`, without building the permutation.
+/// See Kensler, Correlated Multi-Jittered Sampling
+fn permutation_element(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+    (index.wrapping_add(seed)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::SamplerKind;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    #[test]
+    fn values_are_in_the_unit_interval() {
+        for kind in KINDS {
+            let mut sampler = kind.create(64, (16, 16), 7);
+            for index in 0..64 {
+                for pixel in [(0, 0), (5, 9), (15, 15)] {
+                    sampler.start_pixel_sample(pixel.into(), index);
+                    // More dimensions than Halton has bases
+                    for _ in 0..40 {
+                        let (x, y) = sampler.get_2d();
+                        let u = sampler.get_1d();
+                        for value in [x, y, u] {
+                            assert!((0. ..1.).contains(&value), "{:?} gave {}", kind, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn first_values(kind: SamplerKind, seed: u64, pixels: &[(usize, usize)]) -> Vec<f64> {
+        let mut sampler = kind.create(16, (8, 8), seed);
+        let mut values = Vec::new();
+        for &pixel in pixels {
+            for index in 0..4 {
+                sampler.start_pixel_sample(pixel.into(), index);
+                let (x, y) = sampler.get_2d();
+                values.extend([x, y, sampler.get_1d()]);
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn same_seed_gives_same_values() {
+        let pixels = [(0, 0), (4, 1), (7, 6)];
+        for kind in KINDS {
+            let values = first_values(kind, 11, &pixels);
+            assert_eq!(values, first_values(kind, 11, &pixels), "{:?}", kind);
+            assert_ne!(values, first_values(kind, 12, &pixels), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn pixel_positions_are_stratified() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+            let mut sampler = kind.create(16, (8, 8), 3);
+            for pixel in [(0, 0), (3, 6), (7, 7)] {
+                let cells: HashSet<_> = (0..16)
+                    .map(|index| {
+                        sampler.start_pixel_sample(pixel.into(), index);
+                        let (x, y) = sampler.get_2d();
+                        ((x * 4.) as u32, (y * 4.) as u32)
+                    })
+                    .collect();
+                assert_eq!(cells.len(), 16, "{:?} put two of 16 samples in the same cell of a 4x4 grid", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn halton_dimensions_are_stratified() {
+        // Base 2 for x and base 3 for y, each prefix of the sequence fills the intervals of its length
+        let mut sampler = SamplerKind::Halton.create(16, (8, 8), 3);
+        let points: Vec<_> = (0..16)
+            .map(|index| {
+                sampler.start_pixel_sample((2, 5).into(), index);
+                sampler.get_2d()
+            })
+            .collect();
+        let x_intervals: HashSet<_> = points.iter().map(|(x, _)| (x * 16.) as u32).collect();
+        let y_intervals: HashSet<_> = points[..9].iter().map(|(_, y)| (y * 9.) as u32).collect();
+        assert_eq!(x_intervals.len(), 16);
+        assert_eq!(y_intervals.len(), 9);
+    }
+}
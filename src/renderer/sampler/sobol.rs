@@ -0,0 +1,80 @@
+use crate::image::pixel_coordinate::PixelCoordinate;
+
+use super::{hash, pixel_hash, u32_to_unit, Sampler};
+
+/// The first two dimensions of the Sobol sequence with Owen scrambling.
+/// Each pair of dimensions shuffles the sample order differently per pixel,
+/// see Burley, Practical Hash-based Owen Scrambling.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: PixelCoordinate,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: PixelCoordinate::default(), index: 0, dimension: 0 }
+    }
+
+    fn next_seed(&mut self) -> u64 {
+        let seed = pixel_hash(self.seed, self.pixel, self.dimension);
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: PixelCoordinate, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.next_seed();
+        let index = owen_scramble(self.index, seed as u32);
+        u32_to_unit(owen_scramble(index.reverse_bits(), (seed >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.next_seed();
+        let index = owen_scramble(self.index, seed as u32);
+        owen_scrambled_sobol_2d(index, seed >> 32)
+    }
+}
+
+/// Point `index` of the first two Sobol dimensions, with both dimensions Owen scrambled by `seed`
+pub(super) fn owen_scrambled_sobol_2d(index: u32, seed: u64) -> (f64, f64) {
+    (
+        u32_to_unit(owen_scramble(index.reverse_bits(), hash(&[seed, 0]) as u32)),
+        u32_to_unit(owen_scramble(sobol_second_dimension(index), hash(&[seed, 1]) as u32)),
+    )
+}
+
+/// The second Sobol dimension, its direction numbers follow from v[k] = v[k-1] ^ (v[k-1] >> 1).
+/// The first dimension is the bit reversed index.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Nested uniform scrambling of the bits of `value`, each bit is flipped depending on the bits above it
+pub(super) fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut value = value.reverse_bits();
+    // Laine-Karras permutation, only changes bits based on the bits below them
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50b47c);
+    value ^= value.wrapping_mul(0xb82f1e52);
+    value ^= value.wrapping_mul(0xc7afe638);
+    value ^= value.wrapping_mul(0x8d22f6e6);
+    value.reverse_bits()
+}
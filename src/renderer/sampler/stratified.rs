@@ -0,0 +1,59 @@
+use crate::image::pixel_coordinate::PixelCoordinate;
+
+use super::{hash, permutation_element, pixel_hash, u64_to_unit, Sampler};
+
+/// Divides every dimension into strata and puts each sample at a random position in its own stratum.
+/// The strata are visited in a random order per pixel and dimension, so any number of samples
+/// up to the stratum count is well spread.
+pub struct StratifiedSampler {
+    /// Strata along one axis of a 2D sample
+    strata_per_axis: u32,
+    seed: u64,
+    pixel: PixelCoordinate,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        Self {
+            strata_per_axis: (samples_per_pixel as f64).sqrt().ceil().max(1.) as u32,
+            seed,
+            pixel: (0, 0).into(),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The stratum of this sample out of `stratum_count`, and two random numbers for the jitter within it
+    fn next_stratum(&mut self, stratum_count: u32) -> (u32, u64, u64) {
+        let dimension_hash = pixel_hash(self.seed, self.pixel, self.dimension);
+        let stratum = permutation_element(self.index % stratum_count, stratum_count, dimension_hash as u32);
+        let jitter = hash(&[dimension_hash, self.index as u64]);
+        self.dimension += 1;
+        (stratum, jitter, hash(&[jitter]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: PixelCoordinate, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum_count = self.strata_per_axis * self.strata_per_axis;
+        let (stratum, jitter, _) = self.next_stratum(stratum_count);
+        (stratum as f64 + u64_to_unit(jitter)) / stratum_count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let strata = self.strata_per_axis;
+        let (stratum, jitter_x, jitter_y) = self.next_stratum(strata * strata);
+        (
+            ((stratum % strata) as f64 + u64_to_unit(jitter_x)) / strata as f64,
+            ((stratum / strata) as f64 + u64_to_unit(jitter_y)) / strata as f64,
+        )
+    }
+}
@@ -0,0 +1,55 @@
+use std::f64::consts::PI;
+
+use crate::{
+    algebra::{color::REC_709_LUMINANCE, distribution::Distribution2D, vec3::Vec3},
+    material::map::RgbMap,
+    world::background::Background,
+};
+
+/// Picks directions towards a background texture with a density proportional to its luminance,
+/// so a bright sun in the sky lights the scene without the noise of rays finding it by chance
+pub struct EnvironmentLight<'a> {
+    background: &'a Background,
+    distribution: Distribution2D,
+}
+
+impl<'a> EnvironmentLight<'a> {
+    /// `None` for backgrounds without a texture
+    pub fn new(background: &'a Background) -> Option<Self> {
+        let RgbMap::Texture(texture) = &background.map else {
+            return None;
+        };
+        let (width, height) = texture.dimensions();
+        // Rows near the poles are squeezed into less solid angle
+        let function: Vec<f64> = texture
+            .enumerate_pixels()
+            .map(|(_x, y, pixel)| {
+                let sin_theta = ((y as f64 + 0.5) / height as f64 * PI).sin();
+                Vec3::from(pixel).dot(&REC_709_LUMINANCE) * sin_theta
+            })
+            .collect();
+        Some(Self { background, distribution: Distribution2D::new(&function, width as usize) })
+    }
+
+    /// A direction towards the background with its density per solid angle
+    pub fn sample(&self, sample: (f64, f64)) -> (Vec3, f64) {
+        let (uv, uv_pdf) = self.distribution.sample(sample);
+        let direction = self.background.uv_to_direction(uv);
+        (direction, solid_angle_pdf(uv_pdf, uv.1))
+    }
+
+    /// Density per solid angle with which `sample` picks the direction
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let uv = self.background.direction_to_uv(direction);
+        solid_angle_pdf(self.distribution.pdf(uv), uv.1)
+    }
+}
+
+/// The texture covers 2π by π radians, and its rows shrink with the sine of their polar angle
+fn solid_angle_pdf(uv_pdf: f64, v: f64) -> f64 {
+    let sin_theta = (v * PI).sin();
+    if sin_theta <= 0. {
+        return 0.;
+    }
+    uv_pdf / (2. * PI * PI * sin_theta)
+}
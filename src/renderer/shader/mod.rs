@@ -1,17 +1,16 @@
-pub mod shade_package;
+pub mod shade_result;
+mod environment_light;
 mod reflective_model;
 mod refractive_model;
 
-use std::f64::consts::PI;
-
 use crate::{
     algebra::{vec3::Vec3, ray::Ray},
     hit::{Hit, TraceResult},
-    material::map::{GetValueAt, RgbMap},
-    world::World,
+    renderer::{sampler::Sampler, tracer::{trace_package::TracePackage, Tracer}},
+    world::{background::Background, light::Light, World},
 };
 
-use self::{shade_package::ShadePackage, reflective_model::{DiffuseModel, SpecularModel, CookTorrance, SpecularDistributionFunction}, refractive_model::RefractiveModel};
+use self::{environment_light::EnvironmentLight, shade_result::ShadeResult, reflective_model::{DiffuseModel, SpecularModel, CookTorrance, SpecularDistributionFunction}, refractive_model::RefractiveModel};
 
 pub struct Shader<'a> {
     diffuse_model: DiffuseModel,
@@ -19,29 +18,33 @@ pub struct Shader<'a> {
     refractive_model: RefractiveModel,
     // ray_count : usize,
 
-    scene_background: Option<&'a RgbMap>,
+    scene_background: Option<&'a Background>,
+    environment_light: Option<EnvironmentLight<'a>>,
+    lights: &'a [Light],
 }
 
 impl<'a> Shader<'a> {
     pub fn pre_compute(&mut self, world: &'a World) {
         self.scene_background = Some(&world.background);
+        self.environment_light = EnvironmentLight::new(&world.background);
+        self.lights = &world.lights;
         // self.ray_count = self.reflective_model.count() + self.refractive_model.count();
     }
-    pub fn shade_hit(&self, trace_result: &TraceResult, ray : &Ray) -> Vec<ShadePackage> {
+    /// `bsdf_pdf` weighs the emission hit by the ray against light sampling, see `TracePackage::bsdf_pdf`.
+    /// Without `continues` the path ends at the hit, and the light sampled there is not weighed against a next ray.
+    pub fn shade_hit(&self, trace_result: &TraceResult, ray : &Ray, bsdf_pdf: Option<f64>, continues: bool, tracer: &Tracer, sampler: &mut dyn Sampler) -> ShadeResult {
         match trace_result {
-            TraceResult::Hit(hit) => self.parse_hit(hit, ray),
-            TraceResult::Miss => match self.scene_background.unwrap() {
-                RgbMap::Color(color) => vec![(*color).into()],
-                RgbMap::Texture(texture) => {
-                    let u = 0.5 + f64::atan2(ray.direction_unit.z, ray.direction_unit.x) / (2. * PI);
-                    let v = 0.5 - f64::asin(ray.direction_unit.y.clamp(-1.0, 1.0)) / PI;
-                    vec![texture.get_value_at(u, v).into()]
-                }
+            TraceResult::Hit(hit) => self.parse_hit(hit, ray, bsdf_pdf, continues, tracer, sampler),
+            TraceResult::Miss => {
+                // Weighed against sampling the background directly, like the emission of lights
+                let radiance = self.scene_background.unwrap().radiance(ray.direction_unit);
+                let weight = bsdf_pdf.map_or(1., |bsdf_pdf| power_heuristic(bsdf_pdf, self.environment_pdf(ray.direction_unit)));
+                (radiance * weight).into()
             }
         }
     }
-    fn parse_hit(&self, hit: &Hit, ray : &Ray) -> Vec<ShadePackage> {
-        let mut packages : Vec<ShadePackage> = vec![];
+    fn parse_hit(&self, hit: &Hit, ray : &Ray, bsdf_pdf: Option<f64>, continues: bool, tracer: &Tracer, sampler: &mut dyn Sampler) -> ShadeResult {
+        let mut light = Vec3::ZEROS;
         let material = hit.material;
 
         let base_reflectance = (material.ior - 1.).powi(2) / (material.ior + 1.).powi(2);
@@ -56,34 +59,132 @@ impl<'a> Shader<'a> {
         let refraction_factor = material.refraction * (1. - fresnel_reflection);
         let diffuse_factor = (1. - material.specular) * (1. - material.refraction);
 
-
-        // Add refraction
-        if refraction_factor > 0.0001 {
-            self.refractive_model.add_refraction(hit, ray, &mut packages, refraction_factor);
-        }
-
+        // Only refraction continues through back faces
         let hitting_face_from_front = ray.direction_unit.dot(&hit.normal) < 0.;
+        let lobes = Lobes {
+            refraction: if refraction_factor > 0.0001 { refraction_factor } else { 0. },
+            specular: if hitting_face_from_front && specular_factor > 0.0001 { specular_factor } else { 0. },
+            diffuse: if hitting_face_from_front && diffuse_factor > 0.0001 { diffuse_factor * material.diffuse_color.surface_value_at(hit.uv) } else { Vec3::ZEROS },
+            continues,
+        };
+
+        let mut lights_sampled = false;
         if hitting_face_from_front {
-            // Add luminance
-            if material.luminance.sum() > 0.{
-                packages.push(material.luminance.into());
+            // Add luminance, weighed against sampling the light directly if that could have found it too
+            if material.luminance.sum() > 0. {
+                let weight = bsdf_pdf.map_or(1., |bsdf_pdf| power_heuristic(bsdf_pdf, tracer.light_pdf(hit, ray)));
+                light += material.luminance * weight;
             }
 
-            // Add specular
-            if specular_factor > 0.0001 {
-                self.specular_model.add_specular(hit, ray, &mut packages, specular_factor);
-            }
+            // Add light sampled directly, from the emissive triangles and from the background
+            let direct_light = self.sample_direct_light(hit, ray, tracer, &lobes, sampler);
+            let environment_light = self.sample_environment_light(hit, ray, tracer, &lobes, sampler);
+            light += [direct_light, environment_light].into_iter().flatten().sum::<Vec3>();
+            lights_sampled = direct_light.is_some() || environment_light.is_some();
 
-            // Add diffuse
-            if diffuse_factor > 0.0001 {
-                self.diffuse_model.add_diffuse(hit, ray, &mut packages, diffuse_factor * material.diffuse_color);
-            }
-        } 
+            // Add the lights of the world
+            light += self.light_from_world_lights(hit, ray, tracer, &lobes, sampler);
+        }
 
+        ShadeResult { light, next: self.sample_lobe(hit, ray, &lobes, lights_sampled, sampler) }
+    }
 
-        packages
+    /// Continues the path through one part of the material, picked in proportion to the light it carries.
+    /// Dividing by that chance keeps the path's light the same on average as following every part.
+    fn sample_lobe(&self, hit: &Hit, ray: &Ray, lobes: &Lobes, lights_sampled: bool, sampler: &mut dyn Sampler) -> Option<TracePackage> {
+        let [refraction, specular, diffuse] = lobes.probabilities()?;
+        let u = sampler.get_1d();
+        let (package, probability) = if u < refraction {
+            (self.refractive_model.sample_refraction(hit, ray, lobes.refraction), refraction)
+        } else if u < refraction + specular || diffuse <= 0. {
+            (self.specular_model.sample_specular(hit, ray, lobes.specular, lights_sampled, sampler)?, specular)
+        } else {
+            (self.diffuse_model.sample_diffuse(hit, ray, lobes.diffuse, lights_sampled, sampler)?, diffuse)
+        };
+        Some(TracePackage {
+            ray: package.ray,
+            multiplier: package.multiplier / probability,
+            bsdf_pdf: package.bsdf_pdf.map(|pdf| pdf * probability),
+        })
     }
 
+    /// Samples a point on the lights and returns the light arriving from it that the diffuse and specular parts reflect,
+    /// each weighed against their own rays finding the light. `None` when there was nothing to sample,
+    /// then the rays of those parts count all the emission they hit.
+    fn sample_direct_light(&self, hit: &Hit, ray: &Ray, tracer: &Tracer, lobes: &Lobes, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if !tracer.has_lights() || !lobes.reflects() {
+            return None;
+        }
+        let light = tracer.sample_light(sampler.get_1d(), sampler.get_2d())?;
+
+        let to_light = light.position - hit.position;
+        let distance = to_light.magnitude();
+        let direction = to_light / distance;
+        let cos_light = -direction.dot(&light.normal);
+        if cos_light <= 0. {
+            return Some(Vec3::ZEROS);
+        }
+        // Converts the density per area on the light to one per solid angle seen from the hit
+        let light_pdf = light.area_pdf * distance * distance / cos_light;
+
+        let reflectance = self.weighted_reflectance(hit, ray, direction, light_pdf, lobes);
+        if reflectance.sum() <= 0. || tracer.is_occluded(&Ray::between(hit.position, hit.geometric_normal, direction, distance)) {
+            return Some(Vec3::ZEROS);
+        }
+        Some(reflectance * light.luminance / light_pdf)
+    }
+
+    /// Like `sample_direct_light`, for a direction picked towards the bright parts of the background texture
+    fn sample_environment_light(&self, hit: &Hit, ray: &Ray, tracer: &Tracer, lobes: &Lobes, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let environment_light = self.environment_light.as_ref()?;
+        if !lobes.reflects() {
+            return None;
+        }
+        let (direction, light_pdf) = environment_light.sample(sampler.get_2d());
+        if light_pdf <= 0. {
+            return Some(Vec3::ZEROS);
+        }
+
+        let reflectance = self.weighted_reflectance(hit, ray, direction, light_pdf, lobes);
+        if reflectance.sum() <= 0. || tracer.is_occluded(&Ray::leaving(hit.position, hit.geometric_normal, direction)) {
+            return Some(Vec3::ZEROS);
+        }
+        Some(reflectance * self.scene_background.unwrap().radiance(direction) / light_pdf)
+    }
+
+    /// How much of the light arriving from `direction` the diffuse and specular parts reflect back along the ray,
+    /// each weighed against the path continuing through it and finding the light that was sampled with density `light_pdf`
+    fn weighted_reflectance(&self, hit: &Hit, ray: &Ray, direction: Vec3, light_pdf: f64, lobes: &Lobes) -> Vec3 {
+        let [_refraction, specular, diffuse] = lobes.probabilities().unwrap_or([0.; 3]);
+        let weight = |bsdf_pdf: Option<f64>, probability: f64| bsdf_pdf.map_or(0., |bsdf_pdf| power_heuristic(light_pdf, bsdf_pdf * probability));
+        self.diffuse_model.evaluate(hit, direction, lobes.diffuse) * weight(self.diffuse_model.pdf(hit, direction), diffuse)
+            + self.specular_model.evaluate(hit, ray, direction, lobes.specular) * weight(self.specular_model.pdf(hit, ray, direction), specular)
+    }
+
+    /// Density per solid angle with which `sample_environment_light` picks a direction, 0 without a background texture
+    fn environment_pdf(&self, direction: Vec3) -> f64 {
+        self.environment_light.as_ref().map_or(0., |environment_light| environment_light.pdf(direction))
+    }
+
+    /// The light that reaches the hit from every light of the world and is reflected back along the ray.
+    /// Bounced rays cannot find these lights, so they are not weighed against them.
+    fn light_from_world_lights(&self, hit: &Hit, ray: &Ray, tracer: &Tracer, lobes: &Lobes, sampler: &mut dyn Sampler) -> Vec3 {
+        if !lobes.reflects() {
+            return Vec3::ZEROS;
+        }
+        self.lights
+            .iter()
+            .filter_map(|light| light.sample(hit.position, sampler.get_2d()))
+            .map(|sample| {
+                let reflectance = self.diffuse_model.evaluate(hit, sample.direction, lobes.diffuse)
+                    + self.specular_model.evaluate(hit, ray, sample.direction, lobes.specular);
+                if reflectance.sum() <= 0. || tracer.is_occluded(&Ray::between(hit.position, hit.geometric_normal, sample.direction, sample.distance)) {
+                    return Vec3::ZEROS;
+                }
+                reflectance * sample.light
+            })
+            .sum()
+    }
 
     #[inline]
     /// See https://en.wikipedia.org/wiki/Schlick%27s_approximation
@@ -98,20 +199,57 @@ impl<'a> Shader<'a> {
 
     pub(crate) fn clear(&mut self) {
         self.scene_background = None;
+        self.environment_light = None;
+        self.lights = &[];
     }
 }
 
 
+/// The parts of the material at a hit, with the fraction of the light each of them carries
+struct Lobes {
+    refraction: f64,
+    specular: f64,
+    diffuse: Vec3,
+    /// Whether the path goes on through one of the parts
+    continues: bool,
+}
+
+impl Lobes {
+    /// Whether light arriving from outside the surface can be reflected back along the ray
+    fn reflects(&self) -> bool {
+        self.specular > 0. || self.diffuse.sum() > 0.
+    }
+
+    /// Chances of continuing the path through refraction, specular and diffuse, `None` when the path ends
+    fn probabilities(&self) -> Option<[f64; 3]> {
+        let weights = [self.refraction, self.specular, self.diffuse.max()];
+        let total: f64 = weights.iter().sum();
+        (self.continues && total > 0.).then(|| weights.map(|weight| weight / total))
+    }
+}
+
+/// Weight of a sample taken with density `pdf`, when another strategy could have taken it with density `other_pdf`.
+/// See Veach, Robust Monte Carlo Methods for Light Transport Simulation, section 9.2.4
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (squared, other_squared) = (pdf * pdf, other_pdf * other_pdf);
+    if squared + other_squared > 0. {
+        squared / (squared + other_squared)
+    } else {
+        0.
+    }
+}
 
 impl Default for Shader<'_> {
     fn default() -> Self {
         Self {
-            diffuse_model: DiffuseModel::Lambertian(2),
+            diffuse_model: DiffuseModel::Lambertian,
             specular_model: SpecularModel::CookTorrance(
                 CookTorrance{ distribution_function: SpecularDistributionFunction::Ggx, 
                     geometry_function: reflective_model::SpecularGeometryFunction::SchlickGGX }),
             refractive_model: RefractiveModel::None,
             scene_background: Default::default(),
+            environment_light: None,
+            lights: &[],
         }
     }
 }
@@ -1,8 +1,10 @@
 use std::f64::consts::PI;
 
-use crate::{hit::Hit, algebra::{ray::Ray, vec3::Vec3, quaternion::Quaternion}, renderer::tracer::trace_package::TracePackage};
+use crate::{hit::Hit, algebra::{ray::Ray, vec3::Vec3, quaternion::Quaternion}, renderer::{sampler::Sampler, tracer::trace_package::TracePackage}};
+
+/// Smoother surfaces are treated as perfect mirrors, the lights are not sampled for their reflections
+const MIN_SAMPLED_ROUGHNESS: f64 = 0.01;
 
-use super::shade_package::ShadePackage;
 #[allow(dead_code)]
 pub enum SpecularModel {
     None,
@@ -11,10 +13,28 @@ pub enum SpecularModel {
 impl SpecularModel {
    
     #[inline]
-    pub fn add_specular(&self, hit : &Hit, ray: &Ray, package_vec: &mut Vec<ShadePackage>, specular_factor: f64){
+    pub fn sample_specular(&self, hit : &Hit, ray: &Ray, specular_factor: f64, lights_sampled: bool, sampler: &mut dyn Sampler) -> Option<TracePackage>{
+        match self{
+            SpecularModel::None => None,
+            SpecularModel::CookTorrance(cook_torrance) => Some(cook_torrance.sample_specular(hit, ray, specular_factor, lights_sampled, sampler)),
+        }
+    }
+
+    /// Density with which `sample_specular` reflects the ray into `direction`, per solid angle.
+    /// `None` for perfect mirrors, which only reflect into a single direction, and where the density is not known.
+    /// Light sampling skips those and leaves their reflections to the paths.
+    pub fn pdf(&self, hit : &Hit, ray: &Ray, direction: Vec3) -> Option<f64>{
+        match self{
+            SpecularModel::None => Some(0.),
+            SpecularModel::CookTorrance(cook_torrance) => cook_torrance.pdf(hit, ray, direction),
+        }
+    }
+
+    /// The light reflected back along the ray for each unit of light arriving from `direction`, including the cosine term
+    pub fn evaluate(&self, hit : &Hit, ray: &Ray, direction: Vec3, specular_factor: f64) -> Vec3{
         match self{
-            SpecularModel::None => {},
-            SpecularModel::CookTorrance(cook_torrance) => cook_torrance.add_specular(hit, ray, package_vec, specular_factor),
+            SpecularModel::None => Vec3::ZEROS,
+            SpecularModel::CookTorrance(cook_torrance) => cook_torrance.evaluate(hit, ray, direction, specular_factor),
         }
     }
 }
@@ -26,12 +46,38 @@ pub struct CookTorrance {
 
 impl CookTorrance{
     #[inline]
-    pub fn add_specular(&self, hit : &Hit, ray: &Ray, package_vec: &mut Vec<ShadePackage>, specular_factor: f64){
-        let normal = self.distribution_function.micro_facet_normal_sample(hit.material.roughness, &hit.normal);
-        package_vec.push(TracePackage {
-            ray: ray.reflect_specular(normal, hit.position),
+    pub fn sample_specular(&self, hit : &Hit, ray: &Ray, specular_factor: f64, lights_sampled: bool, sampler: &mut dyn Sampler) -> TracePackage{
+        let normal = self.distribution_function.micro_facet_normal_sample(hit.material.roughness, &hit.normal, sampler.get_2d());
+        let reflected_ray = ray.reflect_specular(normal, hit.position, hit.geometric_normal);
+        TracePackage {
+            ray: reflected_ray,
             multiplier: Vec3::uniform(specular_factor) * self.geometry_function.get_shading_factor(&hit.material.roughness, ray, &hit.normal),
-        }.into());
+            bsdf_pdf: if lights_sampled { self.pdf(hit, ray, reflected_ray.direction_unit) } else { None },
+        }
+    }
+
+    pub fn pdf(&self, hit : &Hit, ray: &Ray, direction: Vec3) -> Option<f64>{
+        let roughness = hit.material.roughness;
+        if roughness < MIN_SAMPLED_ROUGHNESS {
+            return None;
+        }
+        // The micro facet normal that reflects the ray into the direction
+        let outgoing = -ray.direction_unit;
+        let micro_facet_normal = (outgoing + direction).normalize();
+        let normal_pdf = self.distribution_function.micro_facet_normal_pdf(roughness, &hit.normal, &micro_facet_normal)?;
+        // Reflecting doubles the angles, which spreads the directions over four times the solid angle
+        Some(normal_pdf / (4. * outgoing.dot(&micro_facet_normal).abs()).max(1e-8))
+    }
+
+    /// The sampled rays are weighed by the geometry term alone, so this is that term times the density of the direction
+    pub fn evaluate(&self, hit : &Hit, ray: &Ray, direction: Vec3, specular_factor: f64) -> Vec3{
+        if direction.dot(&hit.geometric_normal) <= 0. {
+            return Vec3::ZEROS;
+        }
+        match self.pdf(hit, ray, direction) {
+            Some(pdf) => Vec3::uniform(specular_factor * self.geometry_function.get_shading_factor(&hit.material.roughness, ray, &hit.normal) * pdf),
+            None => Vec3::ZEROS,
+        }
     }
 }
 pub enum SpecularGeometryFunction {
@@ -56,11 +102,9 @@ pub enum SpecularDistributionFunction {
 // See https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
 impl SpecularDistributionFunction{
     #[inline]
-    fn micro_facet_normal_sample(&self, roughness: f64, surface_normal : &Vec3) -> Vec3{
+    fn micro_facet_normal_sample(&self, roughness: f64, surface_normal : &Vec3, (random_u, random_v): (f64, f64)) -> Vec3{
         match self{
             SpecularDistributionFunction::Ggx => {
-                let random_u = fastrand::f64();
-                let random_v = fastrand::f64();
 
                 let parallel = 1. / ((roughness*roughness * random_u / (1.-random_u)) + 1. ).sqrt();
                 let flat_radius = (1. - parallel * parallel).sqrt();
@@ -82,36 +126,62 @@ impl SpecularDistributionFunction{
             SpecularDistributionFunction::Phong => todo!(),
         }
     }
+
+    /// Density of the micro facet normals from `micro_facet_normal_sample`, D(h) cos(theta_h).
+    /// `None` for Phong, which has no sampling to match yet.
+    #[inline]
+    fn micro_facet_normal_pdf(&self, roughness: f64, surface_normal : &Vec3, micro_facet_normal: &Vec3) -> Option<f64>{
+        match self{
+            SpecularDistributionFunction::Ggx => {
+                let cos_theta = micro_facet_normal.dot(surface_normal);
+                if cos_theta <= 0. {
+                    return Some(0.);
+                }
+                let alpha_squared = roughness * roughness;
+                let denominator = cos_theta * cos_theta * (alpha_squared - 1.) + 1.;
+                Some(alpha_squared * cos_theta / (PI * denominator * denominator))
+            },
+            SpecularDistributionFunction::Phong => None,
+        }
+    }
 }
 
 #[allow(dead_code)]
 pub enum DiffuseModel {
     None,
-    Lambertian(usize),
+    Lambertian,
 }
 
 impl DiffuseModel{
-    // pub fn count(&self) -> usize{
-    //     match self {
-    //         DiffuseModel::None => 0,
-    //         DiffuseModel::Lambertian(count) => *count,
-    //     }
-    // }
-    
+    /// Density with which `sample_diffuse` sends the ray into `direction`, per solid angle
+    pub fn pdf(&self, hit : &Hit, direction: Vec3) -> Option<f64>{
+        match self{
+            DiffuseModel::None => Some(0.),
+            DiffuseModel::Lambertian => Some(direction.dot(&hit.normal).max(0.) / PI),
+        }
+    }
+
+    /// The light reflected for each unit of light arriving from `direction`, including the cosine term
+    pub fn evaluate(&self, hit : &Hit, direction: Vec3, diffuse_factor: Vec3) -> Vec3{
+        let cos_theta = direction.dot(&hit.normal);
+        match self{
+            DiffuseModel::Lambertian if cos_theta > 0. && direction.dot(&hit.geometric_normal) > 0. => diffuse_factor * (cos_theta / PI),
+            _ => Vec3::ZEROS,
+        }
+    }
+
     #[inline]
-    pub fn add_diffuse(&self, hit : &Hit, ray: &Ray, package_vec: &mut Vec<ShadePackage>, diffuse_factor: Vec3){
+    pub fn sample_diffuse(&self, hit : &Hit, ray: &Ray, diffuse_factor: Vec3, lights_sampled: bool, sampler: &mut dyn Sampler) -> Option<TracePackage>{
         match self{
-            DiffuseModel::None => {},
-            DiffuseModel::Lambertian(count) => {
-                for _ in 0..*count{
-                    let diffuse_factor = diffuse_factor / *count as f64;
-                    package_vec.push(TracePackage {
-                        ray: ray.reflect_diffuse(hit.normal, hit.position),
-                        multiplier: diffuse_factor,
-                    }.into());
-                }
+            DiffuseModel::None => None,
+            DiffuseModel::Lambertian => {
+                let diffuse_ray = ray.reflect_diffuse(hit.normal, hit.position, hit.geometric_normal, sampler.get_2d());
+                Some(TracePackage {
+                    ray: diffuse_ray,
+                    multiplier: diffuse_factor,
+                    bsdf_pdf: if lights_sampled { self.pdf(hit, diffuse_ray.direction_unit) } else { None },
+                })
             },
         }
-        
     }
 }
@@ -0,0 +1,15 @@
+use crate::{renderer::tracer::trace_package::TracePackage, algebra::vec3::Vec3};
+
+/// What shading adds to a path at one surface or at the background
+pub struct ShadeResult{
+    /// Emitted and directly sampled light leaving back along the ray
+    pub light: Vec3,
+    /// The ray the path continues with, `None` where the path ends
+    pub next: Option<TracePackage>,
+}
+
+impl From<Vec3> for ShadeResult{
+    fn from(light: Vec3) -> Self {
+        ShadeResult { light, next: None }
+    }
+}
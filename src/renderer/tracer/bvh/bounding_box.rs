@@ -120,29 +120,6 @@ impl BoundingBox {
             None
         }
     }
-    #[inline]
-    pub fn optimized_distance(&self, ray_origin: &Vec3, inverted_ray_direction: &Vec3) -> Option<f64>{
-        let minimums = f64x4::new(self.minimums.x, self.minimums.y, self.minimums.z, 0.);
-        let maximums = f64x4::new(self.maximums.x, self.maximums.y, self.maximums.z, f64::MAX / 2.);
-        let ray_origin = f64x4::new(ray_origin.x, ray_origin.y, ray_origin.z, 0.);
-        let inverted_ray_direction = f64x4::new(inverted_ray_direction.x, inverted_ray_direction.y, inverted_ray_direction.z, 1.);
-  
-        let min_vertex = minimums - ray_origin;
-        let max_vertex = maximums - ray_origin;
-
-        let distances_to_min_vertex = min_vertex * inverted_ray_direction;
-        let distances_to_max_vertex = max_vertex * inverted_ray_direction;
-
-        let min_distance = f64x4::min(distances_to_min_vertex, distances_to_max_vertex);
-        let max_distance = f64x4::max(distances_to_min_vertex, distances_to_max_vertex);
-
-        if min_distance.max_element() <= max_distance.min_element() {
-            Some(min_distance.max_element())
-        } else {
-            None
-        }
-    }
-
     pub fn split_at(&self, axis: Axis, position : f64) -> (BoundingBox, BoundingBox){
         let mut bb1 = *self;
         let mut bb2 = *self;
@@ -163,3 +140,41 @@ impl Union for &[BoundingBox]{
         self.iter().copied().reduce(|acc, e| acc.union(&e)).unwrap()
     }
 }
+
+/// The bounding boxes of up to four nodes, with each coordinate spread over the lanes of a vector
+/// so a ray is tested against all of them at once
+pub struct WideBoundingBox {
+    minimums: [f64x4; 3],
+    maximums: [f64x4; 3],
+}
+
+impl WideBoundingBox {
+    pub fn new(bounding_boxes: &[BoundingBox]) -> Self {
+        let lanes = |corner: fn(&BoundingBox) -> Vec3, axis: Axis| {
+            let mut lanes = [0.; 4];
+            for (lane, bounding_box) in lanes.iter_mut().zip(bounding_boxes) {
+                *lane = *corner(bounding_box).axis(axis);
+            }
+            f64x4::from_slice_unaligned(&lanes)
+        };
+        WideBoundingBox {
+            minimums: Axis::ALL.map(|axis| lanes(|bounding_box| bounding_box.minimums, axis)),
+            maximums: Axis::ALL.map(|axis| lanes(|bounding_box| bounding_box.maximums, axis)),
+        }
+    }
+
+    /// Distance to where the ray enters each box, and a bit for each box it enters before `reach`.
+    /// The ray is given with each coordinate in all lanes.
+    #[inline]
+    pub fn distances(&self, ray_origin: &[f64x4; 3], inverted_ray_direction: &[f64x4; 3], reach: f64) -> (f64x4, u8) {
+        let mut entry = f64x4::splat(0.);
+        let mut exit = f64x4::splat(reach);
+        for axis in 0..3 {
+            let distances_to_minimums = (self.minimums[axis] - ray_origin[axis]) * inverted_ray_direction[axis];
+            let distances_to_maximums = (self.maximums[axis] - ray_origin[axis]) * inverted_ray_direction[axis];
+            entry = entry.max(distances_to_minimums.min(distances_to_maximums));
+            exit = exit.min(distances_to_minimums.max(distances_to_maximums));
+        }
+        (entry, entry.le(exit).bitmask())
+    }
+}
@@ -1,29 +1,108 @@
 mod bounding_box;
 
+use packed_simd_2::f64x4;
+
 use crate::{algebra::{vec3::Vec3, axis::Axis}, algebra::ray::Ray};
 
-use self::bounding_box::{BoundingBox, Union};
+use self::bounding_box::{BoundingBox, Union, WideBoundingBox};
+
+use super::triangle_hit_parser::{CompactTriangle, ShearedRay, TriangleHit, TriangleHitParser};
+
+/// Children per node, one for each lane of the vectors the bounding boxes are tested with
+const WIDTH: usize = 4;
 
-use super::triangle_hit_parser::TriangleHitParser;
-pub struct BoundedVolume<'a> {
-    bounding_box : BoundingBox,
-    children: BoundedVolumeChildren<'a>,
+/// Nodes deeper than this become leaves, so traversal fits in a fixed size stack
+const MAX_DEPTH: usize = 32;
+
+/// Every node visited leaves at most all but one of its children on the stack
+const STACK_SIZE: usize = (WIDTH - 1) * MAX_DEPTH;
+
+/// Bounding volume hierarchy stored flat: nodes refer to their children and triangles by index.
+/// Triangles are reordered so each leaf owns a contiguous range.
+pub struct BoundedVolumeHierarchy<'a> {
+    /// The root comes first
+    nodes: Vec<BoundedVolume>,
+    /// Vertices of the triangles in leaf order, all traversal reads
+    triangles: Vec<CompactTriangle>,
+    /// The full triangles in the same order, looked up for the hit that was found
+    triangle_data: Vec<TriangleHitParser<'a>>,
 }
 
-pub enum BoundedVolumeChildren<'a> {
-    BoundedVolumes([Box<BoundedVolume<'a>>; 2]),
-    Triangles(Vec<TriangleHitParser<'a>>),
+/// A node with up to `WIDTH` children, whose bounding boxes a ray is tested against at once
+pub struct BoundedVolume {
+    bounding_boxes: WideBoundingBox,
+    children: [BoundedVolumeChildren; WIDTH],
+    child_count: u32,
 }
 
-impl<'a> BoundedVolume<'a> {
+#[derive(Clone, Copy)]
+pub enum BoundedVolumeChildren {
+    /// Index of the child node
+    BoundedVolume(u32),
+    /// Range of triangles
+    Triangles{start: u32, count: u32},
+}
+
+/// Triangles that end up below one child while a node is built
+struct Group<'a> {
+    triangles: Vec<TriangleHitParser<'a>>,
+    bounding_box: BoundingBox,
+    split: Option<(Axis, f64)>,
+}
+
+impl<'a> BoundedVolumeHierarchy<'a> {
     pub fn new(triangles: Vec<TriangleHitParser<'a>>) -> Self {
-        Self::build(triangles, 5)
+        let resolution = 5;
+        let mut hierarchy = BoundedVolumeHierarchy {
+            nodes: Vec::new(),
+            triangles: Vec::with_capacity(triangles.len()),
+            triangle_data: Vec::with_capacity(triangles.len()),
+        };
+        let root = Group::new(triangles, resolution);
+        let root_bounding_box = root.bounding_box;
+        // Traversal starts at a node, so a scene that fits in a single leaf still gets one
+        if let leaf @ BoundedVolumeChildren::Triangles{..} = hierarchy.build(root, resolution, 0) {
+            hierarchy.nodes.push(BoundedVolume::new(&[root_bounding_box], vec![leaf]));
+        }
+        hierarchy
+    }
+
+    /// Adds the nodes for the triangles of `group`, returning what its parent refers to them by
+    fn build(&mut self, group: Group<'a>, resolution: usize, depth: usize) -> BoundedVolumeChildren {
+        if group.split.is_none() || depth + 1 >= MAX_DEPTH {
+            let start = self.triangles.len() as u32;
+            self.triangles.extend(group.triangles.iter().map(CompactTriangle::from));
+            self.triangle_data.extend(group.triangles);
+            return BoundedVolumeChildren::Triangles{start, count: self.triangles.len() as u32 - start};
+        }
+
+        // The binary splits are made as usual, opening up the largest group until the node is full
+        let mut groups = vec![group];
+        while groups.len() < WIDTH {
+            let largest = groups
+                .iter()
+                .enumerate()
+                .filter(|(_index, group)| group.split.is_some())
+                .max_by(|(_, a), (_, b)| a.bounding_box.area().total_cmp(&b.bounding_box.area()))
+                .map(|(index, _group)| index);
+            let Some(largest) = largest else { break };
+            let [group_1, group_2] = groups.remove(largest).split(resolution);
+            groups.splice(largest..largest, [group_1, group_2]);
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(BoundedVolume::new(&[], Vec::new()));
+        let bounding_boxes: Vec<BoundingBox> = groups.iter().map(|group| group.bounding_box).collect();
+        let children = groups.into_iter().map(|group| self.build(group, resolution, depth + 1)).collect();
+        self.nodes[index] = BoundedVolume::new(&bounding_boxes, children);
+        BoundedVolumeChildren::BoundedVolume(index as u32)
     }
 
-    pub fn build(triangles: Vec<TriangleHitParser<'a>>, resolution: usize) -> BoundedVolume<'a>{
+    /// Axis and position of the cut with the lowest Surface Area Heuristic, `None` when the triangles are better
+    /// off in a single leaf
+    fn find_split(triangles: &[TriangleHitParser], own_bounding_box: &BoundingBox, resolution: usize) -> Option<(Axis, f64)> {
         let centroids: Vec<Vec3> = triangles.iter().map(TriangleHitParser::get_center).collect();
         let centroid_bounding_box = BoundingBox::new_from_vec3s(&centroids);
-        let own_bounding_box = BoundingBox::new_from_triangles(&triangles);
         let own_sah = own_bounding_box.area() * triangles.len() as f64;
 
         let mut lowest_sah : (f64, Axis, usize) = (f64::MAX, Axis::X, 0);
@@ -34,7 +113,7 @@ impl<'a> BoundedVolume<'a> {
             let step_size = (max - min) / resolution as f64;
 
             // Find correct bin for each triangle
-            for (centroid, triangle) in centroids.iter().zip(&triangles){
+            for (centroid, triangle) in centroids.iter().zip(triangles){
                 let bin = usize::min(
                     ((centroid.axis(axis) - min) / step_size) as usize, 
                     resolution - 1
@@ -56,85 +135,144 @@ impl<'a> BoundedVolume<'a> {
         }
 
         if own_sah <= lowest_sah.0 + 1e-5 || triangles.len() < 2{
-            BoundedVolume{
-                bounding_box: own_bounding_box,
-                children: BoundedVolumeChildren::Triangles(triangles)
-            }
+            None
         } else {
             let min = centroid_bounding_box.minimums.axis(lowest_sah.1);
             let max = centroid_bounding_box.maximums.axis(lowest_sah.1);
             let step_size = (max - min) / resolution as f64;
-            let split = min + step_size * lowest_sah.2 as f64;
-
-            let (triangles1, triangles2) : (Vec<TriangleHitParser>, Vec<TriangleHitParser>)= triangles.into_iter()
-                .partition(|&triangle| *triangle.get_center().axis(lowest_sah.1) < split);
-            BoundedVolume{
-                bounding_box: own_bounding_box,
-                children: BoundedVolumeChildren::BoundedVolumes([
-                    Box::new(BoundedVolume::build(triangles1, resolution)),
-                    Box::new(BoundedVolume::build(triangles2, resolution)),
-                ])
-            }
+            Some((lowest_sah.1, min + step_size * lowest_sah.2 as f64))
         }
-
     }
 
-    pub fn get_potential_collision(&self, ray: &Ray) -> Option<(&TriangleHitParser, f64)> {
-        let bounding_box_distance = self.bounding_box.distance(ray);
-        bounding_box_distance?;
+    /// The closest triangle along the ray and where it is hit
+    pub fn get_potential_collision(&self, ray: &Ray) -> Option<(&TriangleHitParser<'a>, TriangleHit)> {
+        let sheared_ray = ShearedRay::from(ray);
+        let (origin, inverse_direction) = wide_ray(ray);
+        let mut closest: Option<(usize, TriangleHit)> = None;
+        // Nodes still to visit, the nearest on top
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_size = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            // Children beyond the closest hit so far cannot hold a closer one
+            let reach = closest.map_or(ray.t_max, |(_index, hit)| hit.distance);
+            let (distances, hits) = node.bounding_boxes.distances(&origin, &inverse_direction, reach);
 
-        self.traverse_children(ray, & (1. / ray.direction_unit))
+            // Leaves are searched right away, nodes are visited nearest first
+            let mut nodes_hit = [(0., 0); WIDTH];
+            let mut node_count = 0;
+            for lane in (0..node.child_count as usize).filter(|lane| hits & (1 << lane) != 0) {
+                match node.children[lane] {
+                    BoundedVolumeChildren::BoundedVolume(child) => {
+                        nodes_hit[node_count] = (distances.extract(lane), child);
+                        node_count += 1;
+                    },
+                    BoundedVolumeChildren::Triangles{start, count} => {
+                        let (start, end) = (start as usize, (start + count) as usize);
+                        for (triangle_index, triangle) in self.triangles[start..end].iter().enumerate() {
+                            if let Some(hit) = triangle.get_hit(&sheared_ray) {
+                                let is_closer = match &closest {
+                                    Some((_index, closest_hit)) => hit.distance < closest_hit.distance,
+                                    None => true,
+                                };
+                                if is_closer {
+                                    closest = Some((start + triangle_index, hit));
+                                }
+                            }
+                        }
+                    },
+                }
+            }
+
+            if node_count > 0 {
+                let nodes_hit = &mut nodes_hit[..node_count];
+                nodes_hit.sort_unstable_by(|(a, _), (b, _)| b.total_cmp(a));
+                for &(_distance, child) in &nodes_hit[..node_count - 1] {
+                    stack[stack_size] = child;
+                    stack_size += 1;
+                }
+                index = nodes_hit[node_count - 1].1 as usize;
+                continue;
+            }
+
+            // Continue with the nearest node left. When a hit has since been found in front of it, its children are
+            // cut off by `reach`, which is cheaper than keeping distances on the stack.
+            if stack_size == 0 {
+                return closest.map(|(index, hit)| (&self.triangle_data[index], hit));
+            }
+            stack_size -= 1;
+            index = stack[stack_size] as usize;
+        }
     }
 
-    #[inline]
-    pub fn traverse_children(&self, ray: &Ray, inverse_ray_direction: &Vec3) -> Option<(&TriangleHitParser, f64)>{
-        match &self.children{
-            BoundedVolumeChildren::BoundedVolumes(volumes) => {
-                let distance_0 = volumes[0].bounding_box.optimized_distance(&ray.origin, inverse_ray_direction);
-                let distance_1 = volumes[1].bounding_box.optimized_distance(&ray.origin, inverse_ray_direction);
-
-                match (distance_0, distance_1){
-                    (None, None) => None,
-                    (Some(_), None) => volumes[0].traverse_children(ray, inverse_ray_direction),
-                    (None, Some(_)) => volumes[1].traverse_children(ray, inverse_ray_direction),
-                    // In case both bounding boxes are hit:
-                    (Some(distance_0), Some(distance_1)) => {
-                        let (close, far, far_distance) = 
-                            if distance_0 > distance_1{
-                                (&volumes[1], &volumes[0], distance_0)
-                            } else {
-                                (&volumes[0], &volumes[1], distance_1)
-                            };
-                        // We traverse the closest box first, and if the ray doesn't hit anything, we traverse the far one.
-                        if let Some((triangle_0, distance_0)) = close.traverse_children(ray, inverse_ray_direction) {
-                            // If the far box is closer than the hit in the close box, we traverse the far box.
-                            if far_distance < distance_0{
-                                if let Some((triangle_1, distance_1)) = far.traverse_children(ray, inverse_ray_direction){
-                                    if distance_1 < distance_0{
-                                        return Some((triangle_1, distance_1))
-                                    } 
-                                } 
-                            } 
-                            return Some((triangle_0, distance_0))
+    /// Whether the ray hits any triangle within its `t_max`, returning at the first one found
+    pub fn any_hit(&self, ray: &Ray) -> bool {
+        let sheared_ray = ShearedRay::from(ray);
+        let (origin, inverse_direction) = wide_ray(ray);
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_size = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            let (_distances, hits) = node.bounding_boxes.distances(&origin, &inverse_direction, ray.t_max);
+            for lane in (0..node.child_count as usize).filter(|lane| hits & (1 << lane) != 0) {
+                match node.children[lane] {
+                    BoundedVolumeChildren::BoundedVolume(child) => {
+                        stack[stack_size] = child;
+                        stack_size += 1;
+                    },
+                    BoundedVolumeChildren::Triangles{start, count} => {
+                        let (start, end) = (start as usize, (start + count) as usize);
+                        if self.triangles[start..end].iter().any(|triangle| triangle.intersects(&sheared_ray)) {
+                            return true;
                         }
-                        return far.traverse_children(ray, inverse_ray_direction)
-                    }
+                    },
                 }
-            },
-            BoundedVolumeChildren::Triangles(triangles) => {
-                triangles
-                    .iter()
-                    .map(|triangle| (triangle, triangle.get_hit_distance(ray)))
-                    .filter(|(_triangle, distance)| distance.is_some())
-                    .map(|(triangle, distance)| (triangle, distance.unwrap()))
-                    .reduce(|(closest_triangle, closest_distance), (new_triangle, new_distance)| 
-                        if closest_distance > new_distance{
-                            (new_triangle, new_distance)
-                        } else {
-                            (closest_triangle, closest_distance)
-                        }
-                     )
             }
+
+            if stack_size == 0 {
+                return false;
+            }
+            stack_size -= 1;
+            index = stack[stack_size] as usize;
         }
     }
-}
\ No newline at end of file
+}
+
+impl BoundedVolume {
+    fn new(bounding_boxes: &[BoundingBox], children: Vec<BoundedVolumeChildren>) -> Self {
+        let mut child_array = [BoundedVolumeChildren::Triangles{start: 0, count: 0}; WIDTH];
+        child_array[..children.len()].copy_from_slice(&children);
+        BoundedVolume {
+            bounding_boxes: WideBoundingBox::new(bounding_boxes),
+            children: child_array,
+            child_count: children.len() as u32,
+        }
+    }
+}
+
+impl<'a> Group<'a> {
+    fn new(triangles: Vec<TriangleHitParser<'a>>, resolution: usize) -> Self {
+        let bounding_box = BoundingBox::new_from_triangles(&triangles);
+        let split = BoundedVolumeHierarchy::find_split(&triangles, &bounding_box, resolution);
+        Group { triangles, bounding_box, split }
+    }
+
+    /// The two groups on either side of the split, which must be known
+    fn split(self, resolution: usize) -> [Group<'a>; 2] {
+        let (axis, split) = self.split.unwrap();
+        let (triangles1, triangles2) : (Vec<TriangleHitParser>, Vec<TriangleHitParser>)= self.triangles.into_iter()
+            .partition(|&triangle| *triangle.get_center().axis(axis) < split);
+        [Group::new(triangles1, resolution), Group::new(triangles2, resolution)]
+    }
+}
+
+/// Ray origin and inverse direction with each coordinate in all lanes, for `WideBoundingBox::distances`
+fn wide_ray(ray: &Ray) -> ([f64x4; 3], [f64x4; 3]) {
+    let inverse_direction = 1. / ray.direction_unit;
+    (
+        [f64x4::splat(ray.origin.x), f64x4::splat(ray.origin.y), f64x4::splat(ray.origin.z)],
+        [f64x4::splat(inverse_direction.x), f64x4::splat(inverse_direction.y), f64x4::splat(inverse_direction.z)],
+    )
+}
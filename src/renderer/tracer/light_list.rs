@@ -0,0 +1,85 @@
+use crate::algebra::{color::REC_709_LUMINANCE, vec3::Vec3};
+
+use super::triangle_hit_parser::TriangleHitParser;
+
+/// A point sampled on an emissive triangle
+pub struct LightSample {
+    pub position: Vec3,
+    /// The front side of the triangle, the only side that emits
+    pub normal: Vec3,
+    pub luminance: Vec3,
+    /// Probability density of sampling this point, per unit of area
+    pub area_pdf: f64,
+}
+
+/// The emissive triangles of the world, picked with a probability proportional to the power they emit
+#[derive(Default)]
+pub struct LightList<'a> {
+    lights: Vec<TriangleHitParser<'a>>,
+    /// Running sum of the power of the lights, divided by the total so the last one is 1
+    cumulative_power: Vec<f64>,
+    total_power: f64,
+}
+
+impl<'a> LightList<'a> {
+    pub fn new(triangles: &[TriangleHitParser<'a>]) -> Self {
+        let lights: Vec<TriangleHitParser> = triangles
+            .iter()
+            .filter(|triangle| triangle.material.luminance.sum() > 0. && triangle.area() > 0.)
+            .copied()
+            .collect();
+
+        let powers: Vec<f64> = lights
+            .iter()
+            .map(|triangle| triangle.material.luminance.dot(&REC_709_LUMINANCE) * triangle.area())
+            .collect();
+        let total_power: f64 = powers.iter().sum();
+        let cumulative_power = powers
+            .iter()
+            .scan(0., |sum, power| {
+                *sum += power / total_power;
+                Some(*sum)
+            })
+            .collect();
+
+        Self { lights, cumulative_power, total_power }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_power <= 0.
+    }
+
+    /// Picks a light with `u` by its power, then a uniformly distributed point on it with `point_sample`
+    pub fn sample(&self, u: f64, (point_u, point_v): (f64, f64)) -> Option<LightSample> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = self.cumulative_power.partition_point(|&power| power <= u).min(self.lights.len() - 1);
+        let triangle = &self.lights[index];
+
+        // Folds the unit square onto the triangle without distorting areas
+        let root = point_u.sqrt();
+        let [a, b, c] = triangle.vertices;
+        let position = a * (1. - root) + b * (root * (1. - point_v)) + c * (root * point_v);
+
+        Some(LightSample {
+            position,
+            normal: triangle.normal,
+            luminance: triangle.material.luminance,
+            area_pdf: self.area_pdf(triangle.material.luminance),
+        })
+    }
+
+    /// Density per area with which `sample` picks a point on a light with this luminance.
+    /// Lights are picked by power, which grows with their area as fast as the density of points on them shrinks.
+    pub fn area_pdf(&self, luminance: Vec3) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+        luminance.dot(&REC_709_LUMINANCE) / self.total_power
+    }
+}
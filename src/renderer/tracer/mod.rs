@@ -1,15 +1,17 @@
 use crate::{
-    hit::TraceResult,
-    world::{triangle::Triangle, World, model::{Vertex, VertexNormal}}, algebra::{ray::Ray, vec3::Vec3}, material::Material,
+    hit::{Hit, TraceResult},
+    world::{triangle::Triangle, World, model::{Model, UV}}, algebra::{ray::Ray, vec3::Vec3}, material::Material,
 };
 
 use self::{
-    bvh::BoundedVolume,
+    bvh::BoundedVolumeHierarchy,
+    light_list::{LightList, LightSample},
     pre_computed_world::PreComputedWorld,
     triangle_hit_parser::TriangleHitParser,
 };
 
 mod bvh;
+pub mod light_list;
 mod pre_computed_world;
 mod triangle_hit_parser;
 pub mod trace_package;
@@ -21,21 +23,29 @@ pub struct Tracer<'a> {
 
 impl<'a> Tracer<'a> {
     pub fn pre_compute(&mut self, world: &'a World) {
-        let triangle_hit_parsers : Vec<TriangleHitParser> = world.models
-            .iter()
+        // Sorted by name, so the hierarchy does not depend on the order of the hash map
+        let mut models: Vec<_> = world.models.iter().collect();
+        models.sort_by_key(|(name, _)| *name);
+
+        let triangle_hit_parsers : Vec<TriangleHitParser> = models
+            .into_iter()
             .flat_map(|(_name, model)| {
-                let material = world.materials.get(&model.material_name).unwrap();
-                model.faces.iter().map(|triangle| Tracer::hit_parser_from_triangle(triangle, &model.vertices, &model.vertex_normals, material))
+                let material = world.materials
+                    .get(&model.material_name)
+                    .unwrap_or(&world.materials["base_diffuse"]);
+                model.faces.iter().map(|triangle| Tracer::hit_parser_from_triangle(triangle, model, material))
             })
             .collect();
 
         let face_count = triangle_hit_parsers.len();
-        let bounded_volume_hierarchy = BoundedVolume::new(triangle_hit_parsers);
+        let lights = LightList::new(&triangle_hit_parsers);
+        let light_count = lights.len();
+        let bounded_volume_hierarchy = BoundedVolumeHierarchy::new(triangle_hit_parsers);
         self.pre_computed_world = Some(PreComputedWorld {
-            // triangle_remaining_data,
             bounded_volume_hierarchy,
+            lights,
         });
-        println!("Finished precompute with {} faces", face_count);
+        println!("Finished precompute with {} faces, {} of them emissive", face_count, light_count);
     }
 
 
@@ -49,14 +59,38 @@ impl<'a> Tracer<'a> {
         }
     }
 
+    /// Whether anything lies on the ray between its `t_min` and `t_max`, without looking for the closest hit
+    pub fn is_occluded(&self, ray: &Ray) -> bool {
+        self.pre_computed_world.as_ref().unwrap().bounded_volume_hierarchy.any_hit(ray)
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.pre_computed_world.as_ref().unwrap().lights.is_empty()
+    }
+
+    /// A point on one of the emissive triangles, see `LightList::sample`
+    pub fn sample_light(&self, u: f64, point_sample: (f64, f64)) -> Option<LightSample> {
+        self.pre_computed_world.as_ref().unwrap().lights.sample(u, point_sample)
+    }
+
+    /// Density per solid angle with which `sample_light` picks the point where the ray hit an emissive triangle
+    pub fn light_pdf(&self, hit: &Hit, ray: &Ray) -> f64 {
+        let cos_light = -ray.direction_unit.dot(&hit.geometric_normal);
+        if cos_light <= 0. {
+            return 0.;
+        }
+        let area_pdf = self.pre_computed_world.as_ref().unwrap().lights.area_pdf(hit.material.luminance);
+        area_pdf * hit.distance * hit.distance / cos_light
+    }
+
     pub(crate) fn clear(&mut self){
         self.pre_computed_world = Default::default();
     }
 
-    fn hit_parser_from_triangle(triangle: &Triangle, vertices: &[Vertex], vertex_normals: &[VertexNormal], material: &'a Material) -> TriangleHitParser<'a>{
+    fn hit_parser_from_triangle(triangle: &Triangle, model: &Model, material: &'a Material) -> TriangleHitParser<'a>{
         let vertices : [Vec3; 3] = triangle.vertices
             .iter()
-            .map(|&index| vertices[index])
+            .map(|&index| model.vertices[index])
             .collect::<Vec<_>>()
             .try_into()
             .unwrap();
@@ -66,32 +100,25 @@ impl<'a> Tracer<'a> {
                 Some(indices) => {
                     indices
                     .iter()
-                    .map(|&index| vertex_normals[index])
+                    .map(|&index| model.vertex_normals[index])
                     .collect::<Vec<_>>()
                     .try_into()
                     .unwrap()
                 },
                 None => [triangle.normal; 3],
             };
-            
-
-        let edge_1 = vertices[1] - vertices[0];
-        let edge_2 = vertices[2] - vertices[0];
 
-        let v1 = edge_1 - edge_1.project(&edge_2);
-        let v2 = edge_2 - edge_2.project(&edge_1);
+        let vertex_uvs : [UV; 3] = match triangle.vertex_uvs {
+            Some(indices) => indices.map(|index| model.vertex_uv[index]),
+            None => [(0., 0.); 3],
+        };
 
-        let inv_proj_1 = 1. / edge_1.dot(&v1);
-        let inv_proj_2 = 1. / edge_2.dot(&v2);
 
         TriangleHitParser {
             normal: triangle.normal,
             vertices,
-            inv_proj_1,
-            inv_proj_2,
-            v1,
-            v2,
             vertex_normals,
+            vertex_uvs,
             material,
         }
     }
@@ -1,9 +1,10 @@
 use crate::{algebra::{ray::Ray, vec3::Vec3}, hit::Hit};
 
-use super::bvh::BoundedVolume;
+use super::{bvh::BoundedVolumeHierarchy, light_list::LightList};
 // #[derive(Default)]
 pub struct PreComputedWorld<'a>{
-    pub bounded_volume_hierarchy: BoundedVolume<'a>,
+    pub bounded_volume_hierarchy: BoundedVolumeHierarchy<'a>,
+    pub lights: LightList<'a>,
 }
 
 
@@ -14,13 +15,10 @@ impl PreComputedWorld<'_>{
         potential_collision?;
 
         // If an intersection is found, construct a hit
-        if let Some((triangle, distance)) = potential_collision {
-            let (a, b) = triangle.get_barycentric_a_b(ray);
-            let barycentrics = [1. - a - b, a, b, ];
-
+        if let Some((triangle, hit)) = potential_collision {
             let mut normal = triangle.vertex_normals
                 .iter()
-                .zip(barycentrics.iter())
+                .zip(hit.barycentrics.iter())
                 .map(|(&normal, &barycentric)| normal * barycentric)
                 .sum::<Vec3>()
                 .normalize();
@@ -30,9 +28,12 @@ impl PreComputedWorld<'_>{
            }
 
             return Some(Hit {
-                distance,
-                position: ray.at(distance),
+                distance: hit.distance,
+                // More precise than stepping along the ray, so rays leaving the surface start on it
+                position: triangle.interpolate_position(&hit.barycentrics),
                 normal,
+                geometric_normal: triangle.normal,
+                uv: triangle.interpolate_uv(&hit.barycentrics),
                 material: triangle.material,
             });
         }
@@ -3,4 +3,8 @@ use crate::algebra::{ray::Ray, vec3::Vec3};
 pub struct TracePackage{
     pub ray: Ray,
     pub multiplier : Vec3,
+    /// When the lights were also sampled directly where this ray starts, the density with which the ray's direction
+    /// was picked, times the chance of continuing through the part of the material that picked it.
+    /// The emission it hits is then weighed against light sampling.
+    pub bsdf_pdf: Option<f64>,
 }
\ No newline at end of file
@@ -1,57 +1,33 @@
-use crate::{algebra::{vec3::Vec3, axis::Axis}, algebra::ray::Ray, material::Material};
+use crate::{algebra::{vec3::Vec3, axis::Axis}, algebra::ray::Ray, material::Material, world::model::UV};
 #[derive(Clone, Copy)]
 pub struct TriangleHitParser<'a> {
     pub normal: Vec3,
 
     pub vertices: [Vec3; 3],
 
-    pub inv_proj_1: f64, // Inv projection of edges in barycentric vector
-    pub inv_proj_2: f64,
-
-    pub v1: Vec3, // Barycentric vector
-    pub v2: Vec3,
-
     pub vertex_normals: [Vec3; 3],
+
+    pub vertex_uvs: [UV; 3],
     pub material: &'a  Material
 
 }
 
 impl TriangleHitParser<'_>{
-    pub fn get_hit_distance(&self, ray: &Ray) -> Option<f64> {
-        let predot = self.normal.dot(&ray.direction_unit);
-        if (-0.01..0.01).contains(&predot){
-            return None
-        }
-        let distance_to_plane =
-            self.normal.dot(&(self.vertices[1] - ray.origin)) / predot;
-
-        if distance_to_plane < 0.01 {
-            return None;
-        }
-
-        let point_on_plane = ray.at(distance_to_plane);
-
-        let a = 1. - (self.vertices[1] - point_on_plane).dot(&self.v1) * self.inv_proj_1;
-        if !(0. ..=1.).contains(&a) {
-            return None;
-        }
-
-        let b = 1. - (self.vertices[2] - point_on_plane).dot(&self.v2) * self.inv_proj_2;
-        if !(0. ..=1.).contains(&b) || a + b > 1. {
-            return None;
-        }
-
-        Some(distance_to_plane)
+    /// Point on the triangle with the given weights of its vertices
+    pub fn interpolate_position(&self, [a, b, c]: &[f64; 3]) -> Vec3 {
+        self.vertices[0] * *a + self.vertices[1] * *b + self.vertices[2] * *c
     }
 
-    pub fn get_barycentric_a_b(&self, ray: &Ray) -> (f64, f64){
-        let distance_to_plane =
-            self.normal.dot(&(self.vertices[1] - ray.origin)) / self.normal.dot(&ray.direction_unit);
-        let point_on_plane = ray.at(distance_to_plane);
+    /// Texture coordinates on the triangle with the given weights of its vertices
+    pub fn interpolate_uv(&self, barycentrics: &[f64; 3]) -> UV {
+        self.vertex_uvs
+            .iter()
+            .zip(barycentrics)
+            .fold((0., 0.), |(u, v), ((vertex_u, vertex_v), weight)| (u + vertex_u * weight, v + vertex_v * weight))
+    }
 
-        let a = 1. - (self.vertices[1] - point_on_plane).dot(&self.v1) * self.inv_proj_1;
-        let b = 1. - (self.vertices[2] - point_on_plane).dot(&self.v2) * self.inv_proj_2;
-        (a, b)
+    pub fn area(&self) -> f64{
+        (self.vertices[1] - self.vertices[0]).cross(&(self.vertices[2] - self.vertices[0])).magnitude() / 2.
     }
 
     pub fn get_center(&self) -> Vec3{
@@ -67,3 +43,103 @@ impl TriangleHitParser<'_>{
     }
 
 }
+
+/// Only the vertices of a triangle, all the intersection test reads, so more of them fit in the cache during traversal
+#[derive(Clone, Copy)]
+pub struct CompactTriangle {
+    pub vertices: [Vec3; 3],
+}
+
+impl From<&TriangleHitParser<'_>> for CompactTriangle {
+    fn from(triangle: &TriangleHitParser) -> Self {
+        CompactTriangle { vertices: triangle.vertices }
+    }
+}
+
+impl CompactTriangle {
+    /// Where the ray meets the triangle, found on either side. Watertight: a ray through an edge or vertex
+    /// shared by triangles hits at least one of them, see `ShearedRay`.
+    pub fn get_hit(&self, ray: &ShearedRay) -> Option<TriangleHit> {
+        let ([u, v, w], determinant, scaled_distance) = self.scaled_hit(ray)?;
+        let inverse_determinant = 1. / determinant;
+        let distance = scaled_distance * inverse_determinant;
+        (ray.ray.t_min..=ray.ray.t_max).contains(&distance).then_some(TriangleHit {
+            distance,
+            barycentrics: [u * inverse_determinant, v * inverse_determinant, w * inverse_determinant],
+        })
+    }
+
+    /// Whether the ray hits the triangle within its bounds, without the division `get_hit` needs
+    pub fn intersects(&self, ray: &ShearedRay) -> bool {
+        self.scaled_hit(ray).is_some_and(|(_barycentrics, determinant, scaled_distance)| {
+            // Compared with the bounds scaled alike, keeping in mind the determinant may be negative
+            let (distance, determinant) = (scaled_distance * determinant.signum(), determinant.abs());
+            (ray.ray.t_min * determinant..=ray.ray.t_max * determinant).contains(&distance)
+        })
+    }
+
+    /// Barycentrics and distance of the hit, both still multiplied by the returned determinant.
+    /// The distance is not yet checked against the bounds of the ray.
+    fn scaled_hit(&self, ray: &ShearedRay) -> Option<([f64; 3], f64, f64)> {
+        let [kx, ky, kz] = ray.axes;
+        // Vertices relative to the ray origin, sheared so the ray points along the z axis
+        let [a, b, c] = self.vertices.map(|vertex| {
+            let relative = vertex - ray.ray.origin;
+            let z = *relative.axis(kz);
+            (relative.axis(kx) - ray.shear.x * z, relative.axis(ky) - ray.shear.y * z, ray.shear.z * z)
+        });
+
+        // Edge functions of the 2D triangle around the origin
+        let u = c.0 * b.1 - c.1 * b.0;
+        let v = a.0 * c.1 - a.1 * c.0;
+        let w = b.0 * a.1 - b.1 * a.0;
+        if (u < 0. || v < 0. || w < 0.) && (u > 0. || v > 0. || w > 0.) {
+            return None;
+        }
+        let determinant = u + v + w;
+        if determinant == 0. {
+            return None;
+        }
+        Some(([u, v, w], determinant, u * a.2 + v * b.2 + w * c.2))
+    }
+}
+
+/// Intersection of a ray with a triangle
+#[derive(Clone, Copy)]
+pub struct TriangleHit {
+    pub distance: f64,
+    /// Weights of the three vertices at the point that was hit, summing to 1
+    pub barycentrics: [f64; 3],
+}
+
+/// A ray prepared for the watertight triangle test of Woop, Benthin and Wald, "Watertight Ray/Triangle Intersection"
+/// (2013). Triangles are moved into a space where the ray points along z from the origin, so the test only has to
+/// check on which side of each edge the origin lies. Triangles sharing an edge compute the same value for it.
+pub struct ShearedRay {
+    pub ray: Ray,
+    /// The axes becoming x, y and z, z being the one the ray points along most
+    axes: [Axis; 3],
+    /// Shear of x and y, and scale of z
+    shear: Vec3,
+}
+
+impl From<&Ray> for ShearedRay {
+    fn from(ray: &Ray) -> Self {
+        let direction = ray.direction_unit;
+        let kz = Axis::ALL
+            .into_iter()
+            .max_by(|&a, &b| direction.axis(a).abs().total_cmp(&direction.axis(b).abs()))
+            .unwrap();
+        let (mut kx, mut ky) = (kz.next(), kz.next().next());
+        // Keeps the winding of the triangles
+        if *direction.axis(kz) < 0. {
+            (kx, ky) = (ky, kx);
+        }
+        let z = *direction.axis(kz);
+        ShearedRay {
+            ray: *ray,
+            axes: [kx, ky, kz],
+            shear: Vec3::new(direction.axis(kx) / z, direction.axis(ky) / z, 1. / z),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+mod parser;
+
+use std::{fs, path::Path};
+
+use image::Rgb32FImage;
+
+use crate::{
+    image::export::{check_output, save_render},
+    renderer::{render_settings::RenderSettings, Renderer},
+    world::World,
+};
+
+/// A world together with the settings it should be rendered with
+pub struct Scene {
+    pub world: World,
+    pub settings: RenderSettings,
+}
+
+impl Scene {
+    /// Loads a scene description file, see `scenes/medieval_house.scene` for the format.
+    /// Paths in the file are relative to the file itself.
+    pub fn load(filename: &str) -> Result<Scene, String> {
+        let input = fs::read_to_string(filename)
+            .map_err(|error| format!("Could not read {}: {}", filename, error))?;
+        parser::parse_scene(&input, Path::new(filename))
+    }
+
+    /// Renders the scene at the resolution of its camera to a linear image, without needing a display
+    pub fn render(&self) -> Rgb32FImage {
+        let (width, height) = self.world.camera.resolution();
+        let mut image = Rgb32FImage::new(width, height);
+        Renderer::default().render(&self.world, &mut image, &self.settings);
+        image
+    }
+
+    /// Renders the scene and writes it to a .png, .jpg, .exr, .hdr or .pfm file
+    pub fn render_to_file(&self, path: &str) -> Result<(), String> {
+        // Fail before rendering rather than after
+        check_output(path, self.settings.color_space)?;
+        let settings = &self.settings;
+        save_render(&self.render(), path, settings.tone_mapping, settings.exposure, settings.color_space)
+    }
+}
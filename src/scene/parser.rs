@@ -0,0 +1,411 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    algebra::{quaternion::Quaternion, vec3::Vec3},
+    material::map::RgbMap,
+    renderer::render_settings::RenderSettings,
+    world::{camera::Camera, light::{Light, LightKind}, load_texture, parser::{f64_from_str, vec3_from_str}, sky::Sky, World},
+};
+
+use super::Scene;
+
+/// A keyword line starting a section, followed by the property lines belonging to it
+struct Block<'a> {
+    keyword: &'a str,
+    argument: &'a str,
+    line: usize,
+    properties: Vec<Property<'a>>,
+}
+
+struct Property<'a> {
+    key: &'a str,
+    value: &'a str,
+    line: usize,
+}
+
+pub fn parse_scene(input: &str, path: &Path) -> Result<Scene, String> {
+    let error_at = |line: usize, message: String| format!("{}:{}: {}", path.display(), line, message);
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let resolve = |file: &str| directory.join(file).to_string_lossy().into_owned();
+
+    let blocks = split_blocks(input).map_err(|(line, message)| error_at(line, message))?;
+
+    // Camera and render settings are needed before anything can be added to the world
+    let mut settings = RenderSettings::default();
+    let (mut width, mut height) = (600, 400);
+    let mut fov_degrees = 45.;
+    let mut position = Vec3::ZEROS;
+    let mut look_at = None;
+    // Checks on the settings as a whole point at the render block, the defaults always pass them
+    let mut render_line = 0;
+    for block in &blocks {
+        match block.keyword {
+            "camera" => for property in &block.properties {
+                let result = match property.key {
+                    "position" => vec3_from_str(property.value).map(|value| position = value),
+                    "look_at" => vec3_from_str(property.value).map(|value| look_at = Some((value, property.line))),
+                    "fov" => f64_from_str(property.value).and_then(|value| match value {
+                        value if value > 0. && value < 180. => Ok(value),
+                        _ => Err(format!("Invalid field of view '{}', expected more than 0 and less than 180 degrees", property.value)),
+                    }).map(|value| fov_degrees = value),
+                    _ => Err(format!("Unknown camera property '{}'", property.key)),
+                };
+                result.map_err(|message| error_at(property.line, message))?;
+            },
+            "render" => {
+                render_line = block.line;
+                for property in &block.properties {
+                    let result = match property.key {
+                        "width" => parse_number(property.value).and_then(at_least_one).map(|value| width = value),
+                        "height" => parse_number(property.value).and_then(at_least_one).map(|value| height = value),
+                        "min_samples" => parse_number(property.value).map(|value| settings.min_samples = value),
+                        "max_samples" => parse_number(property.value).and_then(at_least_one).map(|value| settings.max_samples = value),
+                        "noise_threshold" => f64_from_str(property.value).and_then(|value| match value {
+                            value if value >= 0. && value.is_finite() => Ok(value),
+                            _ => Err(format!("Invalid noise threshold '{}'", property.value)),
+                        }).map(|value| settings.noise_threshold = value),
+                        "sampler" => property.value.parse().map(|value| settings.sampler = value),
+                        "filter" => property.value.parse().map(|value| settings.filter = value),
+                        "seed" => parse_number(property.value).map(|value| settings.seed = value),
+                        "time_limit" => f64_from_str(property.value).and_then(duration_from_seconds).map(|value| settings.time_limit = Some(value)),
+                        "bounces" => parse_number(property.value).map(|value| settings.max_bounces = value),
+                        "tone_mapping" => property.value.parse().map(|value| settings.tone_mapping = value),
+                        "exposure" => f64_from_str(property.value).and_then(|value| match value {
+                            value if value.is_finite() => Ok(value),
+                            _ => Err(format!("Invalid exposure '{}'", property.value)),
+                        }).map(|value| settings.exposure = value),
+                        "color_space" => property.value.parse().map(|value| settings.color_space = value),
+                        _ => Err(format!("Unknown render property '{}'", property.key)),
+                    };
+                    result.map_err(|message| error_at(property.line, message))?;
+                }
+            },
+            _ => {}
+        }
+    }
+    if settings.min_samples > settings.max_samples {
+        return Err(error_at(render_line, "min_samples must not be more than max_samples".to_string()));
+    }
+    settings.sampler
+        .check_sample_count(settings.max_samples, (width, height))
+        .map_err(|message| error_at(render_line, message))?;
+
+    let mut camera = Camera::with_resolution(fov_degrees.to_radians(), width, height);
+    camera.position = position;
+    if let Some((look_at, line)) = look_at {
+        camera.look_at(look_at).map_err(|message| error_at(line, message))?;
+    }
+    let mut world = World::with_camera(camera);
+
+    // Models first, so material blocks can override the materials from their libraries
+    let mut material_overrides = vec![];
+    for block in blocks.iter().filter(|block| block.keyword == "model") {
+        let model_names = world
+            .import_3d_file(&resolve(block.argument))
+            .map_err(|message| error_at(block.line, message))?;
+
+        let mut scale = Vec3::ONES;
+        let mut rotation = Vec3::ZEROS;
+        let mut translation = Vec3::ZEROS;
+        for property in &block.properties {
+            let result = match property.key {
+                "mtllib" => world.import_material_file(&resolve(property.value)),
+                "usemtl" => {
+                    material_overrides.push((property, model_names.clone()));
+                    Ok(())
+                },
+                "scale" => scale_from_str(property.value).map(|value| scale = value),
+                "rotate" => vec3_from_str(property.value).map(|value| rotation = value),
+                "translate" => vec3_from_str(property.value).map(|value| translation = value),
+                _ => Err(format!("Unknown model property '{}'", property.key)),
+            };
+            result.map_err(|message| error_at(property.line, message))?;
+        }
+
+        let rotation = Quaternion::from_euler_angles(&(rotation * std::f64::consts::PI / 180.));
+        for name in &model_names {
+            world.models.get_mut(name).unwrap().transform(scale, rotation, translation);
+        }
+    }
+
+    for block in blocks.iter().filter(|block| block.keyword == "material") {
+        let material = world
+            .materials
+            .entry(block.argument.to_string())
+            .or_default();
+        for property in &block.properties {
+            let result = match property.key {
+                "diffuse_color" => vec3_from_str(property.value).map(|value| material.diffuse_color = value.into()),
+                "diffuse_texture" => load_texture(&resolve(property.value)).map(|texture| material.diffuse_color = texture.into()),
+                "specular_color" => vec3_from_str(property.value).map(|value| material.specular_color = value),
+                "luminance" => vec3_from_str(property.value).map(|value| material.luminance = value),
+                "specular" => f64_from_str(property.value).map(|value| material.specular = value),
+                "roughness" => f64_from_str(property.value).map(|value| material.roughness = value),
+                "refraction" => f64_from_str(property.value).map(|value| material.refraction = value),
+                "ior" => f64_from_str(property.value).map(|value| material.ior = value),
+                _ => Err(format!("Unknown material property '{}'", property.key)),
+            };
+            result.map_err(|message| error_at(property.line, message))?;
+        }
+    }
+
+    for (property, model_names) in material_overrides {
+        if !world.materials.contains_key(property.value) {
+            return Err(error_at(property.line, format!("Unknown material '{}'", property.value)));
+        }
+        for name in model_names {
+            world.models.get_mut(&name).unwrap().material_name = property.value.to_string();
+        }
+    }
+
+    for block in blocks.iter().filter(|block| block.keyword == "background") {
+        let (kind, value) = block.argument.split_once(' ').unwrap_or((block.argument, ""));
+        let applicable: &[&str] = match kind {
+            "color" | "image" => &["rotation"],
+            "sky" => &["sun_direction", "turbidity", "ground_albedo", "sun_intensity"],
+            _ => return Err(error_at(block.line, format!("Unknown background '{}', expected 'color', 'image' or 'sky'", kind))),
+        };
+        let mut sky = Sky::default();
+        let mut sun_intensity = 1.;
+        for property in &block.properties {
+            let result = match property.key {
+                "intensity" => f64_from_str(property.value).map(|value| world.background.intensity = value),
+                key if !applicable.contains(&key) => Err(format!("Unknown property '{}' for {} backgrounds", key, kind)),
+                "rotation" => f64_from_str(property.value).map(|value| world.background.rotation = value.to_radians()),
+                "sun_direction" => vec3_from_str(property.value).map(|value| sky.sun_direction = value),
+                "turbidity" => f64_from_str(property.value).map(|value| sky.turbidity = value),
+                "ground_albedo" => f64_from_str(property.value).map(|value| sky.ground_albedo = value),
+                "sun_intensity" => f64_from_str(property.value).map(|value| sun_intensity = value),
+                _ => unreachable!(),
+            };
+            result.map_err(|message| error_at(property.line, message))?;
+        }
+
+        let result = match kind {
+            "color" => vec3_from_str(value).map(|color| world.background.map = RgbMap::Color(color)),
+            "image" => world.import_skybox_file(&resolve(value.trim())),
+            _ if sky.sun_direction.magnitude_squared() == 0. => Err("The sun has no direction".to_string()),
+            _ => {
+                // The sun is not part of the texture but a light, so it casts sharp shadows without noise
+                world.background.map = sky.to_map(&world.background);
+                world.lights.extend(sky.sun_light(world.background.intensity * sun_intensity));
+                Ok(())
+            },
+        };
+        result.map_err(|message| error_at(block.line, message))?;
+    }
+
+    for block in blocks.iter().filter(|block| block.keyword == "light") {
+        let applicable: &[&str] = match block.argument {
+            "point" => &["position"],
+            "spot" => &["position", "direction", "look_at", "cone"],
+            "directional" => &["direction", "angular_diameter"],
+            "rectangle" => &["position", "direction", "look_at", "size"],
+            "disk" => &["position", "direction", "look_at", "radius"],
+            _ => return Err(error_at(block.line, format!(
+                "Unknown light '{}', expected point, spot, directional, rectangle or disk",
+                block.argument
+            ))),
+        };
+        let mut color = Vec3::ONES;
+        let mut intensity = 1.;
+        let mut position = Vec3::ZEROS;
+        let mut direction = -Vec3::Y;
+        let mut look_at = None;
+        let (mut inner_angle, mut outer_angle) = (30., 45.);
+        let mut angular_diameter = 0.53;
+        let (mut width, mut height) = (1., 1.);
+        let mut radius = 0.5;
+        for property in &block.properties {
+            let result = match property.key {
+                "color" => vec3_from_str(property.value).and_then(|value| match value {
+                    value if [value.x, value.y, value.z].iter().all(|channel| *channel >= 0. && channel.is_finite()) => Ok(value),
+                    _ => Err(format!("Invalid light color '{}'", property.value)),
+                }).map(|value| color = value),
+                "intensity" => f64_from_str(property.value).and_then(|value| match value {
+                    value if value >= 0. && value.is_finite() => Ok(value),
+                    _ => Err(format!("Invalid light intensity '{}'", property.value)),
+                }).map(|value| intensity = value),
+                key if !applicable.contains(&key) => Err(format!("Unknown property '{}' for {} lights", key, block.argument)),
+                "position" => vec3_from_str(property.value).map(|value| position = value),
+                "direction" => vec3_from_str(property.value).map(|value| direction = value),
+                "look_at" => vec3_from_str(property.value).map(|value| look_at = Some(value)),
+                "cone" => pair_from_str(property.value).and_then(|value| match value {
+                    (inner, outer) if 0. <= inner && inner <= outer && outer <= 180. => Ok((inner, outer)),
+                    _ => Err(format!("Invalid cone '{}', expected 0 <= inner <= outer <= 180 degrees", property.value)),
+                }).map(|value| (inner_angle, outer_angle) = value),
+                "angular_diameter" => f64_from_str(property.value).and_then(|value| match value {
+                    value if (0. ..180.).contains(&value) => Ok(value),
+                    _ => Err(format!("Invalid angular diameter '{}', expected 0 to less than 180 degrees", property.value)),
+                }).map(|value| angular_diameter = value),
+                "size" => pair_from_str(property.value).and_then(|value| match value {
+                    (width, height) if width > 0. && height > 0. && (width * height).is_finite() => Ok((width, height)),
+                    _ => Err(format!("Invalid light size '{}'", property.value)),
+                }).map(|value| (width, height) = value),
+                "radius" => f64_from_str(property.value).and_then(|value| match value {
+                    value if value > 0. && value.is_finite() => Ok(value),
+                    _ => Err(format!("Invalid light radius '{}'", property.value)),
+                }).map(|value| radius = value),
+                _ => unreachable!(),
+            };
+            result.map_err(|message| error_at(property.line, message))?;
+        }
+        if let Some(look_at) = look_at {
+            direction = look_at - position;
+        }
+        if direction.magnitude_squared() == 0. {
+            return Err(error_at(block.line, "The light has no direction".to_string()));
+        }
+
+        let kind = match block.argument {
+            "point" => LightKind::Point { position },
+            "spot" => LightKind::Spot {
+                position,
+                direction,
+                inner_angle: f64::to_radians(inner_angle),
+                outer_angle: f64::to_radians(outer_angle),
+            },
+            "directional" => LightKind::Directional { direction, angular_diameter: f64::to_radians(angular_diameter) },
+            "rectangle" => LightKind::Rectangle { position, direction, width, height },
+            _ => LightKind::Disk { position, direction, radius },
+        };
+        world.lights.push(Light { kind, color, intensity });
+    }
+
+    Ok(Scene { world, settings })
+}
+
+fn split_blocks(input: &str) -> Result<Vec<Block<'_>>, (usize, String)> {
+    let mut blocks: Vec<Block> = vec![];
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split_once('#').map_or(line, |(content, _comment)| content).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (keyword, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        match keyword {
+            "camera" | "render" => {
+                if !argument.is_empty() {
+                    return Err((line_number, format!("'{}' does not take an argument", keyword)));
+                }
+            },
+            "model" | "material" | "background" | "light" => {
+                if argument.is_empty() {
+                    return Err((line_number, format!("'{}' needs an argument", keyword)));
+                }
+            },
+            _ => match blocks.last_mut() {
+                Some(block) => {
+                    block.properties.push(Property { key: keyword, value: argument, line: line_number });
+                    continue;
+                },
+                None => return Err((line_number, format!("Property '{}' outside of a section", keyword))),
+            },
+        }
+        blocks.push(Block { keyword, argument, line: line_number, properties: vec![] });
+    }
+    Ok(blocks)
+}
+
+fn parse_number<T: std::str::FromStr>(input: &str) -> Result<T, String> {
+    input
+        .parse::<T>()
+        .map_err(|_| format!("Invalid number '{}'", input))
+}
+
+fn at_least_one<T: PartialOrd + From<u8>>(value: T) -> Result<T, String> {
+    match value {
+        value if value >= T::from(1) => Ok(value),
+        _ => Err("Must be at least 1".to_string()),
+    }
+}
+
+fn duration_from_seconds(seconds: f64) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("Invalid duration '{}'", seconds))
+}
+
+fn pair_from_str(input: &str) -> Result<(f64, f64), String> {
+    match input.split_whitespace().map(f64_from_str).collect::<Result<Vec<f64>, String>>()?[..] {
+        [first, second] => Ok((first, second)),
+        _ => Err(format!("Expected two numbers, got '{}'", input)),
+    }
+}
+
+/// Either a single uniform scale or one scale per axis
+fn scale_from_str(input: &str) -> Result<Vec3, String> {
+    match f64_from_str(input) {
+        Ok(scale) => Ok(Vec3::uniform(scale)),
+        Err(_) => vec3_from_str(input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::parse_scene;
+
+    fn error_of(input: &str) -> String {
+        match parse_scene(input, Path::new("scenes/test.scene")) {
+            Ok(_) => panic!("Parsed the invalid scene\n{}", input),
+            Err(message) => message,
+        }
+    }
+
+    #[test]
+    fn parses_scenes_without_models() {
+        let input = "camera\n    position 0 0 5\n    look_at 0 0 0\nrender\n    width 8\n    height 4\nlight point\n    position 0 2 0\n";
+        assert!(parse_scene(input, Path::new("scenes/test.scene")).is_ok());
+    }
+
+    #[test]
+    fn errors_point_at_the_property() {
+        assert_eq!(
+            error_of("# Comment\n\ncamera\n    fov 180\n"),
+            "scenes/test.scene:4: Invalid field of view '180', expected more than 0 and less than 180 degrees"
+        );
+        assert_eq!(
+            error_of("render\n    width 8\n    exposure inf\n"),
+            "scenes/test.scene:3: Invalid exposure 'inf'"
+        );
+        assert_eq!(
+            error_of("light directional\n    direction 0 -1 0\n    angular_diameter 180\n"),
+            "scenes/test.scene:3: Invalid angular diameter '180', expected 0 to less than 180 degrees"
+        );
+        assert_eq!(
+            error_of("light point\n    cone 10 20\n"),
+            "scenes/test.scene:2: Unknown property 'cone' for point lights"
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_block() {
+        assert_eq!(error_of("width 8\n"), "scenes/test.scene:1: Property 'width' outside of a section");
+        assert_eq!(
+            error_of("camera\n    fov 40\n\nrender\n    min_samples 8\n    max_samples 4\n"),
+            "scenes/test.scene:4: min_samples must not be more than max_samples"
+        );
+        assert_eq!(
+            error_of("light spot\n    position 0 1 0\n    look_at 0 1 0\n"),
+            "scenes/test.scene:1: The light has no direction"
+        );
+        assert_eq!(
+            error_of("background plain\n"),
+            "scenes/test.scene:1: Unknown background 'plain', expected 'color', 'image' or 'sky'"
+        );
+    }
+
+    #[test]
+    fn camera_errors_point_at_look_at() {
+        assert_eq!(
+            error_of("camera\n    position 0 1 0\n    look_at 0 1 0\n"),
+            "scenes/test.scene:3: The camera cannot look at its own position"
+        );
+        assert_eq!(
+            error_of("camera\n    position 0 1 0\n    look_at 0 5 0\n"),
+            "scenes/test.scene:3: The camera cannot look straight up or down"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::f64::consts::PI;
+
+use crate::{algebra::vec3::Vec3, material::map::{GetValueAt, RgbMap}};
+
+/// What rays leaving the scene see, and the light arriving at the scene from all around.
+/// Textures are equirectangular, with the horizon in the middle row.
+#[derive(Debug)]
+pub struct Background {
+    pub map: RgbMap,
+    /// Turns the background around the vertical axis, in radians
+    pub rotation: f64,
+    /// Multiplies the color of the background
+    pub intensity: f64,
+}
+
+impl Background {
+    /// The light arriving from the background in a direction
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        match &self.map {
+            RgbMap::Color(color) => *color * self.intensity,
+            RgbMap::Texture(texture) => {
+                let (u, v) = self.direction_to_uv(direction);
+                texture.get_value_at(u, v) * self.intensity
+            },
+        }
+    }
+
+    /// Texture coordinates where the background is looked up for a direction
+    pub fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let u = 0.5 + (f64::atan2(direction.z, direction.x) - self.rotation) / (2. * PI);
+        let v = 0.5 - f64::asin(direction.y.clamp(-1.0, 1.0)) / PI;
+        (u.rem_euclid(1.), v)
+    }
+
+    /// The direction looking at texture coordinates `(u, v)`
+    pub fn uv_to_direction(&self, (u, v): (f64, f64)) -> Vec3 {
+        let phi = (u - 0.5) * 2. * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        RgbMap::Color(Vec3::ZEROS).into()
+    }
+}
+
+impl From<RgbMap> for Background {
+    fn from(map: RgbMap) -> Self {
+        Background { map, rotation: 0., intensity: 1. }
+    }
+}
@@ -17,16 +17,35 @@ pub struct Camera {
 #[allow(dead_code)]
 impl Camera {
     pub fn new(fov_radians_horizontal: f64, image : &RgbImage) -> Self {
+        Camera::with_resolution(fov_radians_horizontal, image.width(), image.height())
+    }
+
+    pub fn with_resolution(fov_radians_horizontal: f64, width: u32, height: u32) -> Self {
         let pixel_size =
-            (fov_radians_horizontal / 2.0).tan() / (image.width() as f64 / 2.0);
+            (fov_radians_horizontal / 2.0).tan() / (width as f64 / 2.0);
         Camera {
             position: Vec3::new(0., 0., 0.),
-            rotation_quaternion: Quaternion::new(),
+            rotation_quaternion: Quaternion::IDENTITY,
             pixel_size,
-            image_size : (image.width(), image.height())
+            image_size : (width, height)
         }
     }
 
+    pub fn resolution(&self) -> (u32, u32) {
+        self.image_size
+    }
+
+    /// Changes the image size while keeping the horizontal field of view
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.pixel_size *= self.image_size.0 as f64 / width as f64;
+        self.image_size = (width, height);
+    }
+
+    pub fn set_fov(&mut self, fov_radians_horizontal: f64) {
+        self.pixel_size =
+            (fov_radians_horizontal / 2.0).tan() / (self.image_size.0 as f64 / 2.0);
+    }
+
     // pub fn with_pixel_size(location: Vec3, pixel_size: f64) -> Self {
     //     Camera {
     //         position: location,
@@ -43,18 +62,23 @@ impl Camera {
             )
             .normalize();
         self.rotation_quaternion.rotate_vector(&mut pixel_normal);
-        Ray {
-            origin: self.position,
-            direction_unit: pixel_normal,
-        }
+        Ray::new(self.position, pixel_normal)
     }
-    pub fn look_at(&mut self, position: Vec3) {
+    /// Fails for the camera's own position and for points straight above or below it, which leave the view direction undefined
+    pub fn look_at(&mut self, position: Vec3) -> Result<(), String> {
         let up = Vec3::Y;
+        if (position - self.position).magnitude_squared() == 0. {
+            return Err("The camera cannot look at its own position".to_string());
+        }
         let direction = (position - self.position).normalize();
         let v = direction - up * up.dot(&direction);
+        if v.magnitude_squared() < 1e-12 {
+            return Err("The camera cannot look straight up or down".to_string());
+        }
         let q = Quaternion::from_unit_vectors(&Vec3::Z, &v);
         self.rotation_quaternion =
             Quaternion::from_unit_vectors(&v, &direction) * q;
+        Ok(())
     }
 }
 
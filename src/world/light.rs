@@ -0,0 +1,114 @@
+use std::f64::consts::PI;
+
+use crate::algebra::vec3::Vec3;
+
+/// A light source that is not part of a model. Rays bouncing through the scene do not see these lights,
+/// they only reach surfaces through the shadow rays sent towards them.
+#[derive(Debug, Clone)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    /// Multiplies the color. For point and spot lights the light reaching a surface facing them at distance 1,
+    /// for directional lights the light reaching a surface facing them, for area lights the luminance of their surface.
+    pub intensity: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum LightKind {
+    /// Shines equally in all directions from a single point
+    Point { position: Vec3 },
+    /// A point light shining into `direction`, fading out from `inner_angle` to `outer_angle` away from it, in radians
+    Spot { position: Vec3, direction: Vec3, inner_angle: f64, outer_angle: f64 },
+    /// Light from very far away travelling into `direction`, like sunlight.
+    /// It comes from a disk in the sky `angular_diameter` radians across, which softens the shadows.
+    Directional { direction: Vec3, angular_diameter: f64 },
+    /// A `width` by `height` rectangle centered at `position`, shining from the side `direction` points to.
+    /// The width is horizontal, unless the rectangle faces straight up or down.
+    Rectangle { position: Vec3, direction: Vec3, width: f64, height: f64 },
+    /// A disk centered at `position`, shining from the side `direction` points to
+    Disk { position: Vec3, direction: Vec3, radius: f64 },
+}
+
+/// A direction from a point towards a light
+pub struct LightSample {
+    pub direction: Vec3,
+    /// Distance to the sampled point of the light, infinite for directional lights
+    pub distance: f64,
+    /// The light arriving from the direction, divided by the density with which the direction was picked
+    pub light: Vec3,
+}
+
+impl Light {
+    /// Picks a point on the light as seen from `position`, using `(u, v)` for lights with a size.
+    /// `None` when the light does not shine towards the position.
+    pub fn sample(&self, position: Vec3, (u, v): (f64, f64)) -> Option<LightSample> {
+        let emitted = self.color * self.intensity;
+        match self.kind {
+            LightKind::Point { position: light_position } => {
+                let (direction, distance) = direction_and_distance(position, light_position);
+                Some(LightSample { direction, distance, light: emitted / (distance * distance) })
+            },
+            LightKind::Spot { position: light_position, direction: spot_direction, inner_angle, outer_angle } => {
+                let (direction, distance) = direction_and_distance(position, light_position);
+                let falloff = smooth_step(outer_angle.cos(), inner_angle.cos(), -direction.dot(&spot_direction.normalize()));
+                (falloff > 0.).then(|| LightSample { direction, distance, light: emitted * (falloff / (distance * distance)) })
+            },
+            LightKind::Directional { direction, angular_diameter } => {
+                // Uniform over the cone of directions towards the disk in the sky
+                let cos_max = (angular_diameter / 2.).cos();
+                let cos_theta = 1. - u * (1. - cos_max);
+                let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+                let phi = 2. * PI * v;
+                let towards_light = -direction.normalize();
+                let (tangent, bitangent) = orthonormal_basis(towards_light);
+                let direction = towards_light * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta;
+                Some(LightSample { direction, distance: f64::INFINITY, light: emitted })
+            },
+            LightKind::Rectangle { position: center, direction, width, height } => {
+                let normal = direction.normalize();
+                let (tangent, bitangent) = orthonormal_basis(normal);
+                let point = center + tangent * ((u - 0.5) * width) + bitangent * ((v - 0.5) * height);
+                area_light_sample(position, point, normal, width * height, emitted)
+            },
+            LightKind::Disk { position: center, direction, radius } => {
+                let normal = direction.normalize();
+                let (tangent, bitangent) = orthonormal_basis(normal);
+                let (r, phi) = (radius * u.sqrt(), 2. * PI * v);
+                let point = center + (tangent * phi.cos() + bitangent * phi.sin()) * r;
+                area_light_sample(position, point, normal, PI * radius * radius, emitted)
+            },
+        }
+    }
+}
+
+fn direction_and_distance(from: Vec3, to: Vec3) -> (Vec3, f64) {
+    let difference = to - from;
+    let distance = difference.magnitude();
+    (difference / distance, distance)
+}
+
+/// A point picked uniformly on a light with the given area, converted to a direction from `position`
+fn area_light_sample(position: Vec3, point: Vec3, normal: Vec3, area: f64, luminance: Vec3) -> Option<LightSample> {
+    let (direction, distance) = direction_and_distance(position, point);
+    let cos_light = -direction.dot(&normal);
+    // The density per solid angle is distance² / (area cos_light)
+    (cos_light > 0.).then(|| LightSample { direction, distance, light: luminance * (area * cos_light / (distance * distance)) })
+}
+
+/// Two unit vectors perpendicular to the normal and each other, the first one horizontal unless the normal is vertical
+fn orthonormal_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let tangent = if normal.cross(&Vec3::Y).magnitude_squared() > 1e-12 {
+        Vec3::Y.cross(&normal).normalize()
+    } else {
+        Vec3::X
+    };
+    (tangent, normal.cross(&tangent))
+}
+
+fn smooth_step(edge_0: f64, edge_1: f64, x: f64) -> f64 {
+    if edge_1 <= edge_0 {
+        return if x >= edge_1 { 1. } else { 0. };
+    }
+    let t = ((x - edge_0) / (edge_1 - edge_0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
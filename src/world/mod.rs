@@ -1,16 +1,19 @@
-use std::{fmt::Display, fs, collections::HashMap};
+use std::{fmt::Display, fs::{self, File}, collections::HashMap, io::BufReader, path::Path};
+pub mod background;
 pub mod camera;
+pub mod light;
 pub mod model;
+pub mod sky;
 pub mod vertex;
 pub mod triangle;
-mod parser;
+pub(crate) mod parser;
 
-use image::io::Reader;
+use image::{codecs::hdr::HdrDecoder, io::Reader, ColorType, ImageFormat, ImageResult, Rgb32FImage};
 
-use crate::{algebra::{vec3::Vec3, color::SpaceCast}, material::{map::RgbMap, Material}};
+use crate::{algebra::{vec3::Vec3, color::SpaceCast}, material::Material};
 
 use self::{
-    camera::Camera, model::Model,
+    background::Background, camera::Camera, light::Light, model::Model,
 };
 
 
@@ -20,9 +23,11 @@ pub struct VertexColor(Vec3);
 
 pub struct World {
     pub camera: Camera,
-    pub background: RgbMap,
+    pub background: Background,
     pub materials: HashMap<String, Material>,
     pub models: HashMap<String, Model>,
+    /// Lights besides the emissive materials of the models
+    pub lights: Vec<Light>,
     pub vertex_normals: Vec<VertexNormal>,
 }
 
@@ -30,53 +35,77 @@ impl<'a> World {
     pub fn with_camera(camera: Camera) -> Self {
         World {
             camera,
-            background: RgbMap::Color(Vec3::ZEROS),
+            background: Background::default(),
             models: HashMap::new(),
+            lights: vec![],
             vertex_normals: vec![],
             materials: HashMap::from([("base_diffuse".to_string(), Material::base_diffuse())]),
         }
     }
-    pub fn import_3d_file(&'a mut self, filename: &str) -> Result<String, String> {
-        let (_name, extension) = filename.split_once('.').unwrap_or(("", ""));
-        let file_string = fs::read_to_string(filename).unwrap();
+    /// Imports the objects in a 3D file as models, returning their names
+    pub fn import_3d_file(&'a mut self, filename: &str) -> Result<Vec<String>, String> {
+        let (_name, extension) = filename.rsplit_once('.').unwrap_or(("", ""));
+        let file_string = fs::read_to_string(filename)
+            .map_err(|error| format!("Could not read {}: {}", filename, error))?;
         match extension {
             // "stl" => parser::parse_ascii_stl(file_string.as_str(), self, model_name),
-            "obj" => parser::parse_ascii_obj(file_string.as_str(), self)?,
-            _ => Err("Extension not supported")?,
-        };
-        Ok("Success.".to_string())
+            "obj" => parser::parse_ascii_obj(file_string.as_str(), self),
+            _ => Err("Extension not supported".to_string()),
+        }
     }
 
     pub fn import_material_file(&'a mut self, filename: &str) -> Result<(), String> {
-        let (_name, extension) = filename.split_once('.').unwrap_or(("", ""));
-        let file_string = fs::read_to_string(filename).unwrap();
+        let (_name, extension) = filename.rsplit_once('.').unwrap_or(("", ""));
+        let file_string = fs::read_to_string(filename)
+            .map_err(|error| format!("Could not read {}: {}", filename, error))?;
         match extension {
             // "stl" => parser::parse_ascii_stl(file_string.as_str(), self, model_name),
-            "mtl" => parser::parse_mtl(file_string.as_str(), self)?,
+            "mtl" => parser::parse_mtl(file_string.as_str(), self, Path::new(filename).parent().unwrap_or(Path::new("")))?,
             _ => Err("Extension not supported")?,
         };
         Ok(())
     }
 
+    /// Loads an equirectangular background, see `load_texture`
     pub fn import_skybox_file(&'a mut self, filename: &str) -> Result<(), String> {
-        self.background = Reader::open(filename)
-            .map_err(|_| "Could not open file".to_string())?
-            .decode()
-            .unwrap()
-            .into_rgb32f()
-            .srgb_to_linear()
-            .into();
-
+        self.background.map = load_texture(filename)?.into();
         Ok(())
     }
 }
 
+/// Loads an image as linear colors. Radiance .hdr and OpenEXR files keep their full range,
+/// 8-bit formats like .png and .jpg are decoded from sRGB.
+pub(crate) fn load_texture(filename: &str) -> Result<Rgb32FImage, String> {
+    let reader = Reader::open(filename)
+        .map_err(|error| format!("Could not open {}: {}", filename, error))?;
+
+    match reader.format() {
+        // `decode` would turn Radiance files into 8-bit images, clamping everything brighter than 1
+        Some(ImageFormat::Hdr) => read_radiance_file(reader.into_inner()),
+        _ => reader.decode().map(|image| {
+            let is_linear = matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+            let texture = image.into_rgb32f();
+            if is_linear { texture } else { texture.srgb_to_linear() }
+        }),
+    }
+    .map_err(|error| format!("Could not decode {}: {}", filename, error))
+}
+
+fn read_radiance_file(reader: BufReader<File>) -> ImageResult<Rgb32FImage> {
+    let decoder = HdrDecoder::new(reader)?;
+    let (width, height) = (decoder.metadata().width, decoder.metadata().height);
+    let pixels = decoder.read_image_hdr()?;
+    let values = pixels.into_iter().flat_map(|pixel| pixel.0).collect();
+    Ok(Rgb32FImage::from_raw(width, height, values).expect("the decoder returns one pixel per position"))
+}
+
 impl Display for World {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
-            "World with {} models",
-            self.models.len()
+            "World with {} models and {} lights",
+            self.models.len(),
+            self.lights.len()
         )
         .unwrap();
         writeln!(f, "{}", self.camera).unwrap();
@@ -1,6 +1,6 @@
 use std::{fmt::{Display, Debug}, ops::AddAssign};
 
-use crate::{algebra::vec3::Vec3};
+use crate::algebra::{quaternion::Quaternion, vec3::Vec3};
 
 use super::{triangle::Triangle};
 
@@ -16,6 +16,24 @@ pub struct Model{
     pub material_name: String,
 }
 
+impl Model{
+    /// Scales, then rotates, then translates all vertices and normals of the model
+    pub fn transform(&mut self, scale: Vec3, rotation: Quaternion, translation: Vec3){
+        for vertex in &mut self.vertices{
+            *vertex *= scale;
+            rotation.rotate_vector(vertex);
+            *vertex += translation;
+        }
+        // Normals transform with the inverse transpose, which for a scale is its inverse
+        let face_normals = self.faces.iter_mut().map(|face| &mut face.normal);
+        for normal in self.vertex_normals.iter_mut().chain(face_normals){
+            *normal /= scale;
+            rotation.rotate_vector(normal);
+            *normal = normal.normalize();
+        }
+    }
+}
+
 impl Debug for Model{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Display::fmt(&self, f)
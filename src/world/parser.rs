@@ -1,13 +1,16 @@
 // use std::sync::{Arc, RwLock};
 
 use core::result::Result;
+use std::path::Path;
 
 use crate::{algebra::vec3::Vec3, material::Material};
 
-use super::{model::{Model, Vertex}, triangle::Triangle, World, VertexNormal};
+use super::{load_texture, model::{Model, Vertex}, triangle::Triangle, World, VertexNormal};
 
-pub fn parse_ascii_obj(input : &str, world : &mut World) -> Result<(), String>{
+/// Parses the objects in an .obj file into the world, returning the names of the created models
+pub fn parse_ascii_obj(input : &str, world : &mut World) -> Result<Vec<String>, String>{
     let lines = input.lines();
+    let mut model_names = vec![];
 
     let (mut v_counter, mut vn_counter, mut vt_counter) = (0,0,0);
     let mut current_model = None;
@@ -18,8 +21,10 @@ pub fn parse_ascii_obj(input : &str, world : &mut World) -> Result<(), String>{
         match prefix{
             "#" => {}, // Comment, ignore for parsing
             "o" | "g" => { // Currently treating groups (g) as objects
-                world.models.insert(data.to_string(), Model::default());
-                current_model = world.models.get_mut(&data.to_string());
+                let name = unique_model_name(world, data);
+                world.models.insert(name.clone(), Model::default());
+                current_model = world.models.get_mut(&name);
+                model_names.push(name);
                 current_smoothing = true;
             },
             "v" => { // Vertex
@@ -74,10 +79,21 @@ pub fn parse_ascii_obj(input : &str, world : &mut World) -> Result<(), String>{
             &_ => {}
         }
     }
-    Ok(())
+    Ok(model_names)
 }
 
-fn vec3_from_str(input : &str) -> Result<Vec3, String>{
+/// Objects with a name that is already in use, e.g. when importing a file twice, get a numbered suffix
+fn unique_model_name(world: &World, name: &str) -> String{
+    let mut unique_name = name.to_string();
+    let mut counter = 1;
+    while world.models.contains_key(&unique_name){
+        counter += 1;
+        unique_name = format!("{}.{}", name, counter);
+    }
+    unique_name
+}
+
+pub(crate) fn vec3_from_str(input : &str) -> Result<Vec3, String>{
     let mut numbers = input
         .split_ascii_whitespace()
         .flat_map(str::parse::<f64>);
@@ -88,7 +104,7 @@ fn vec3_from_str(input : &str) -> Result<Vec3, String>{
     )
 }
 
-fn f64_from_str(input : &str) -> Result<f64, String>{
+pub(crate) fn f64_from_str(input : &str) -> Result<f64, String>{
     str::parse::<f64>(input)
         .ok()
         .ok_or("Invalid number".to_string())
@@ -198,7 +214,8 @@ fn calculate_normal_for_face(face: &mut Triangle, vertices: &[Vertex], normals:
     face.normal = normal;
 }
 
-pub(crate) fn parse_mtl(input: &str, world: &mut World) -> Result<(), String> {
+/// Texture maps are looked up relative to `directory`, the one of the .mtl file
+pub(crate) fn parse_mtl(input: &str, world: &mut World, directory: &Path) -> Result<(), String> {
     let lines = input.lines();
     let mut current_material = None;
     for (index, line) in lines.enumerate(){
@@ -216,7 +233,13 @@ pub(crate) fn parse_mtl(input: &str, world: &mut World) -> Result<(), String> {
                 // Ambient is not used since GI replaces it
             },
             "Kd" => {
-                current_material.as_mut().unwrap().diffuse_color = vec3_from_str(data)?;
+                current_material.as_mut().unwrap().diffuse_color = vec3_from_str(data)?.into();
+            },
+            "map_Kd" => {
+                // Options like -s come before the file name
+                let filename = data.split_whitespace().last().ok_or("Missing texture file".to_string())?;
+                let texture = load_texture(&directory.join(filename).to_string_lossy())?;
+                current_material.as_mut().unwrap().diffuse_color = texture.into();
             },
             "Ks" => {
                 current_material.as_mut().unwrap().specular_color = vec3_from_str(data)?;
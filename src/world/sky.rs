@@ -0,0 +1,191 @@
+use std::f64::consts::PI;
+
+use image::{Rgb, Rgb32FImage};
+
+use crate::{algebra::vec3::Vec3, material::map::RgbMap};
+
+use super::{background::Background, light::{Light, LightKind}};
+
+/// Scene units per kcd/m², the unit of the sky model, so a white surface in the midday sun is about 1
+const LUMINANCE_SCALE: f64 = 0.025;
+
+/// Illuminance of the sun above the atmosphere in klx, the irradiance of the sun light before scaling
+const SUN_ILLUMINANCE: f64 = 128.;
+
+/// The sun is a disk about half a degree across
+const SUN_ANGULAR_DIAMETER: f64 = 0.53 * PI / 180.;
+
+/// Wavelengths in µm for the red, green and blue attenuation of the sunlight
+const WAVELENGTHS: [f64; 3] = [0.68, 0.55, 0.44];
+
+/// Size of the equirectangular texture the sky is baked into
+const TEXTURE_SIZE: (u32, u32) = (512, 256);
+
+/// Daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight" (1999).
+/// The sky is baked into a background texture, the sun itself is a directional light.
+#[derive(Debug, Clone)]
+pub struct Sky {
+    /// Points towards the sun. Below the horizon there is no sunlight and the sky is kept at sunrise.
+    pub sun_direction: Vec3,
+    /// Haziness of the air, from 2 for a clear sky to 10 for a hazy one. The model is valid from 1.7 to 10.
+    pub turbidity: f64,
+    /// Fraction of the light reflected by the ground, which is seen below the horizon
+    pub ground_albedo: f64,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Sky { sun_direction: Vec3::new(0.5, 0.7, 0.3).normalize(), turbidity: 3., ground_albedo: 0.3 }
+    }
+}
+
+impl Sky {
+    /// The sun as a directional light with the color of its light after passing through the atmosphere,
+    /// `None` when it is below the horizon
+    pub fn sun_light(&self, intensity: f64) -> Option<Light> {
+        let sun = self.sun_direction.normalize();
+        if sun.y <= 0. {
+            return None;
+        }
+        Some(Light {
+            kind: LightKind::Directional { direction: -sun, angular_diameter: SUN_ANGULAR_DIAMETER },
+            color: self.sun_transmittance(),
+            intensity: SUN_ILLUMINANCE * LUMINANCE_SCALE * intensity,
+        })
+    }
+
+    /// The sky as a texture for `background`, looked up through its texture coordinates
+    pub fn to_map(&self, background: &Background) -> RgbMap {
+        let (width, height) = TEXTURE_SIZE;
+        let ground = self.ground_radiance();
+        let texture = Rgb32FImage::from_fn(width, height, |x, y| {
+            let uv = ((x as f64 + 0.5) / width as f64, (y as f64 + 0.5) / height as f64);
+            let direction = background.uv_to_direction(uv);
+            let radiance = if direction.y < 0. { ground } else { self.sky_radiance(direction) };
+            Rgb([radiance.x as f32, radiance.y as f32, radiance.z as f32])
+        });
+        texture.into()
+    }
+
+    /// Light arriving from the sky in a direction above the horizon, in scene units and linear sRGB.
+    /// Does not include the sun.
+    fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        let theta = direction.y.clamp(0., 1.).acos();
+        let gamma = direction.dot(&self.sun()).clamp(-1., 1.).acos();
+        let (sun_zenith, turbidity) = (self.sun_zenith(), self.turbidity());
+
+        let [x_zenith, y_zenith, luminance_zenith] = zenith_xy_luminance(sun_zenith, turbidity);
+        let [x_perez, y_perez, luminance_perez] = perez_coefficients(turbidity);
+        let relative = |coefficients: &[f64; 5]| perez(coefficients, theta, gamma) / perez(coefficients, 0., sun_zenith);
+        xy_luminance_to_rgb(
+            x_zenith * relative(&x_perez),
+            y_zenith * relative(&y_perez),
+            luminance_zenith * relative(&luminance_perez) * LUMINANCE_SCALE,
+        )
+    }
+
+    /// The sun direction, raised to the horizon when it is below
+    fn sun(&self) -> Vec3 {
+        let sun = self.sun_direction.normalize();
+        if sun.y >= 0. {
+            return sun;
+        }
+        let horizontal = Vec3::new(sun.x, 0., sun.z);
+        if horizontal.magnitude_squared() > 0. { horizontal.normalize() } else { Vec3::X }
+    }
+
+    fn sun_zenith(&self) -> f64 {
+        self.sun().y.clamp(0., 1.).acos()
+    }
+
+    fn turbidity(&self) -> f64 {
+        self.turbidity.clamp(1.7, 10.)
+    }
+
+    /// Fraction of the sunlight left after Rayleigh and aerosol scattering, from appendix A.2 of the paper
+    fn sun_transmittance(&self) -> Vec3 {
+        let sun_zenith = self.sun_zenith();
+        let zenith_degrees = sun_zenith.to_degrees();
+        let optical_mass = 1. / (sun_zenith.cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity() - 0.04586;
+        let [red, green, blue] = WAVELENGTHS.map(|wavelength| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * optical_mass).exp();
+            let aerosol = (-beta * wavelength.powf(-1.3) * optical_mass).exp();
+            rayleigh * aerosol
+        });
+        Vec3::new(red, green, blue)
+    }
+
+    /// The ground reflects the sun and sky light falling onto it equally in all directions
+    fn ground_radiance(&self) -> Vec3 {
+        let sun_irradiance = self.sun_light(1.).map_or(Vec3::ZEROS, |sun| sun.color * (sun.intensity * self.sun_direction.normalize().y));
+        (self.sky_irradiance() + sun_irradiance) * (self.ground_albedo / PI)
+    }
+
+    /// Light from the sky falling onto the ground, summed over rings of the sky
+    fn sky_irradiance(&self) -> Vec3 {
+        let (rings, segments) = (32, 64);
+        let ring_height = PI / 2. / rings as f64;
+        let segment_width = 2. * PI / segments as f64;
+        let mut irradiance = Vec3::ZEROS;
+        for ring in 0..rings {
+            let theta = (ring as f64 + 0.5) * ring_height;
+            for segment in 0..segments {
+                let phi = (segment as f64 + 0.5) * segment_width;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                irradiance += self.sky_radiance(direction) * (theta.cos() * theta.sin() * ring_height * segment_width);
+            }
+        }
+        irradiance
+    }
+}
+
+/// Chromaticity and luminance in kcd/m² of the sky straight up
+fn zenith_xy_luminance(sun_zenith: f64, turbidity: f64) -> [f64; 3] {
+    let chi = (4. / 9. - turbidity / 120.) * (PI - 2. * sun_zenith);
+    let luminance = (4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192;
+
+    let angles = [sun_zenith.powi(3), sun_zenith.powi(2), sun_zenith, 1.];
+    let polynomial = |rows: [[f64; 4]; 3]| {
+        let [squared, linear, constant] = rows.map(|row| row.iter().zip(angles).map(|(a, b)| a * b).sum::<f64>());
+        turbidity * turbidity * squared + turbidity * linear + constant
+    };
+    let x = polynomial([
+        [0.00166, -0.00375, 0.00209, 0.],
+        [-0.02903, 0.06377, -0.03202, 0.00394],
+        [0.11693, -0.21196, 0.06052, 0.25886],
+    ]);
+    let y = polynomial([
+        [0.00275, -0.00610, 0.00317, 0.],
+        [-0.04214, 0.08970, -0.04153, 0.00516],
+        [0.15346, -0.26756, 0.06670, 0.26688],
+    ]);
+    [x, y, luminance]
+}
+
+/// Coefficients A to E of the Perez sky distribution for x, y and luminance
+fn perez_coefficients(turbidity: f64) -> [[f64; 5]; 3] {
+    let t = turbidity;
+    [
+        [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+        [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+    ]
+}
+
+/// Perez distribution for a direction `theta` from the zenith and `gamma` from the sun
+fn perez([a, b, c, d, e]: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let cos_theta = theta.cos().max(1e-3);
+    (1. + a * (b / cos_theta).exp()) * (1. + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+fn xy_luminance_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3 {
+    let (x_tristimulus, z_tristimulus) = (x / y * luminance, (1. - x - y) / y * luminance);
+    Vec3::new(
+        3.2406 * x_tristimulus - 1.5372 * luminance - 0.4986 * z_tristimulus,
+        -0.9689 * x_tristimulus + 1.8758 * luminance + 0.0415 * z_tristimulus,
+        0.0557 * x_tristimulus - 0.2040 * luminance + 1.0570 * z_tristimulus,
+    )
+    .ew_max(&Vec3::ZEROS)
+}
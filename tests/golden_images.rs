@@ -0,0 +1,100 @@
+//! Renders the small scenes in `tests/scenes` with a fixed seed and compares them against the
+//! reference images in `tests/references`.
+//!
+//! When a render differs too much, the render and an amplified difference image are written to
+//! `target/tmp/golden_images`. After an intended change to the renderer, write new references with
+//! `UPDATE_GOLDEN_IMAGES=1 cargo test --release --test golden_images` and check them before committing.
+
+use std::{env, fs, path::{Path, PathBuf}};
+
+use image::{Rgb, RgbImage};
+use raytracer::{image::display::to_display_image, scene::Scene};
+
+/// Largest allowed root mean square difference of the sRGB values, between 0 and 1.
+/// Leaves room for floating point differences between platforms, not for changed noise.
+const TOLERANCE: f64 = 0.01;
+
+/// Differences in the diff image are multiplied by this to make small ones visible
+const DIFF_SCALE: f64 = 8.;
+
+#[test]
+fn cornell_box() {
+    check_against_reference("cornell_box");
+}
+
+#[test]
+fn glass_sphere() {
+    check_against_reference("glass_sphere");
+}
+
+#[test]
+fn mirror_quad() {
+    check_against_reference("mirror_quad");
+}
+
+#[test]
+fn textured_quad() {
+    check_against_reference("textured_quad");
+}
+
+#[test]
+fn sky() {
+    check_against_reference("sky");
+}
+
+fn check_against_reference(name: &str) {
+    let test_directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let scene_path = test_directory.join("scenes").join(format!("{}.scene", name));
+    let reference_path = test_directory.join("references").join(format!("{}.png", name));
+
+    let scene = Scene::load(&scene_path.to_string_lossy()).unwrap();
+    let render = to_display_image(&scene.render(), scene.settings.tone_mapping, scene.settings.exposure);
+
+    if env::var_os("UPDATE_GOLDEN_IMAGES").is_some() {
+        render.save(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = image::open(&reference_path)
+        .unwrap_or_else(|error| panic!("Could not open {}: {}", reference_path.display(), error))
+        .into_rgb8();
+    assert_eq!(render.dimensions(), reference.dimensions(), "Render and reference of {} differ in size", name);
+
+    let error = root_mean_square_error(&render, &reference);
+    if error > TOLERANCE {
+        let output_directory = write_failure_images(name, &render, &reference);
+        panic!(
+            "{} differs from its reference by {:.4}, more than {}. See {} for the render and the difference",
+            name,
+            error,
+            TOLERANCE,
+            output_directory.display()
+        );
+    }
+}
+
+fn root_mean_square_error(render: &RgbImage, reference: &RgbImage) -> f64 {
+    let squared_error: f64 = render
+        .as_raw()
+        .iter()
+        .zip(reference.as_raw())
+        .map(|(&a, &b)| ((a as f64 - b as f64) / 255.).powi(2))
+        .sum();
+    (squared_error / render.as_raw().len() as f64).sqrt()
+}
+
+fn write_failure_images(name: &str, render: &RgbImage, reference: &RgbImage) -> PathBuf {
+    let output_directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden_images");
+    fs::create_dir_all(&output_directory).unwrap();
+
+    let difference = RgbImage::from_fn(render.width(), render.height(), |x, y| {
+        let (a, b) = (render.get_pixel(x, y), reference.get_pixel(x, y));
+        Rgb([0, 1, 2].map(|channel| {
+            ((a[channel] as f64 - b[channel] as f64).abs() * DIFF_SCALE).min(255.) as u8
+        }))
+    });
+
+    render.save(output_directory.join(format!("{}.png", name))).unwrap();
+    difference.save(output_directory.join(format!("{}_diff.png", name))).unwrap();
+    output_directory
+}